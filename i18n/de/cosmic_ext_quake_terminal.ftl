@@ -3,3 +3,20 @@ settings-title = Quake Terminal Einstellungen
 settings-terminal = Terminal
 terminal-args = Zusätzliche Argumente
 terminal-args-placeholder = z.B. --option wert
+terminal-theme = Farbschema
+terminal-theme-placeholder = Schema-Datei oder Name
+reset-terminal = Standard-Terminal verwenden
+terminal-missing = { $command } ist nicht installiert. Wähle unten ein installiertes Terminal und schalte erneut um.
+monitor = Bildschirm
+monitor-focused = Aktueller Bildschirm
+monitor-primary = Primär
+window-title = Fenstertitel
+window-title-placeholder = Vom Terminal gesetzter Titel
+shell = Shell
+shell-placeholder = Deine Login-Shell
+settings-diagnostics = Offene Fenster
+diagnostics-ours = { $title } (Quake-Terminal)
+exclude-region = Freihalten
+exclude-region-placeholder = x y Breite Höhe, in %
+launch-prefix = Starten über
+launch-prefix-placeholder = z. B. nice -n 10
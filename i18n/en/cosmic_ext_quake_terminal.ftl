@@ -3,3 +3,20 @@ settings-title = Quake Terminal Settings
 settings-terminal = Terminal
 terminal-args = Extra arguments
 terminal-args-placeholder = e.g. --option value
+terminal-theme = Theme
+terminal-theme-placeholder = Theme file or name
+reset-terminal = Use default terminal
+terminal-missing = { $command } is not installed. Pick an installed terminal below, then toggle again.
+monitor = Monitor
+monitor-focused = Current output
+monitor-primary = Primary
+window-title = Window title
+window-title-placeholder = Title set by the terminal
+shell = Shell
+shell-placeholder = Your login shell
+settings-diagnostics = Open windows
+diagnostics-ours = { $title } (quake terminal)
+exclude-region = Keep clear
+exclude-region-placeholder = x y width height, in %
+launch-prefix = Launch through
+launch-prefix-placeholder = e.g. nice -n 10
@@ -3,3 +3,20 @@ settings-title = Impostazioni Quake Terminal
 settings-terminal = Terminale
 terminal-args = Argomenti aggiuntivi
 terminal-args-placeholder = es. --opzione valore
+terminal-theme = Tema
+terminal-theme-placeholder = File o nome del tema
+reset-terminal = Usa terminale predefinito
+terminal-missing = { $command } non è installato. Scegli un terminale installato qui sotto, poi riprova.
+monitor = Schermo
+monitor-focused = Schermo attuale
+monitor-primary = Principale
+window-title = Titolo della finestra
+window-title-placeholder = Titolo impostato dal terminale
+shell = Shell
+shell-placeholder = La tua shell di login
+settings-diagnostics = Finestre aperte
+diagnostics-ours = { $title } (terminale quake)
+exclude-region = Lascia libero
+exclude-region-placeholder = x y larghezza altezza, in %
+launch-prefix = Avvia tramite
+launch-prefix-placeholder = es. nice -n 10
@@ -1,10 +1,18 @@
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
 use clap::Parser;
 use cosmic::app::{Core, Settings, Task};
-use cosmic::cosmic_config::{self, CosmicConfigEntry};
+use cosmic::cosmic_config::{self, ConfigGet, CosmicConfigEntry};
 use cosmic::iced::event::{self, Event};
+use cosmic::iced::platform_specific::runtime::wayland::layer_surface::{
+    IcedOutput, SctkLayerSurfaceSettings,
+};
+use cosmic::iced::platform_specific::shell::commands::layer_surface::{
+    destroy_layer_surface, get_layer_surface, Anchor, KeyboardInteractivity, Layer,
+};
 use cosmic::iced::window;
 use cosmic::iced::Alignment;
 use cosmic::iced::Length;
@@ -13,44 +21,125 @@ use cosmic::widget::{self, container, header_bar, scrollable, settings, text, te
 use cosmic::{Application, ApplicationExt, Element};
 use serde::{Deserialize, Serialize};
 
-use crate::config::{QuakeConfig, CONFIG_VERSION};
+use crate::config::{
+    DetachStrategy, Monitor, Position, QuakeConfig, Region, SpawnBehavior, UnfocusedToggle,
+    CONFIG_VERSION,
+};
 use crate::fl;
+use crate::geometry;
 use crate::process;
-use crate::wayland::{self, ToplevelEvent, WaylandController};
+use crate::stats::{self, Stats};
+use crate::wayland::{
+    self, OutputInfo, ToplevelEvent, ToplevelSummary, WaylandController, WaylandOptions,
+    WorkspaceInfo,
+};
 
-/// (command, display_name, icon_name)
-const KNOWN_TERMINALS: &[(&str, &str, &str)] = &[
-    ("cosmic-term", "cosmic-terminal", "com.system76.CosmicTerm"),
-    ("alacritty", "alacritty", "Alacritty"),
-    ("kitty", "kitty", "kitty"),
-    ("foot", "foot", "foot"),
-    ("wezterm", "wezterm", "org.wezfurlong.wezterm"),
-    ("ghostty", "ghostty", "com.mitchellh.ghostty"),
-];
+pub const APP_ID: &str = "com.github.m0rf30.CosmicExtQuakeTerminal";
 
-const APP_ID: &str = "com.github.m0rf30.CosmicExtQuakeTerminal";
+/// Initial size of the settings window.
+const SETTINGS_SIZE: cosmic::iced::Size = cosmic::iced::Size::new(500.0, 450.0);
+
+/// Settings window width below which the compact layout is used.
+const COMPACT_WIDTH: f32 = 400.0;
+
+/// How long `close_command` gets to close the terminal before SIGTERM.
+const CLOSE_GRACE: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// How often terminals that are no longer tracked are checked for exit.
+const REAP_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long to wait for the terminal window before each rescan.
+const WINDOW_SCAN_INTERVAL: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// How often in a row the Wayland loop is restarted after panicking, or
+/// reconnected after losing its connection, before giving up.
+const MAX_WAYLAND_RESTARTS: u32 = 3;
+/// Time the compositor gets to come back before reconnecting to it
+const RECONNECT_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// How long the compositor's reports may still reflect the state from
+/// before our last show or hide.
+const COMMAND_SETTLE: std::time::Duration = std::time::Duration::from_millis(500);
 
 #[derive(Parser, Debug, Serialize, Deserialize, Clone)]
 #[command(name = "cosmic-ext-quake-terminal")]
 #[command(about = "Quake-style dropdown terminal for COSMIC Desktop")]
 pub struct Args {
     #[command(subcommand)]
-    pub subcommand: Option<QuakeAction>,
+    pub subcommand: Option<CliCommand>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, clap::Subcommand)]
+pub enum CliCommand {
+    #[command(flatten)]
+    Action(QuakeAction),
+    /// Read or change a config value
+    Config {
+        #[command(subcommand)]
+        op: ConfigOp,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, clap::Subcommand)]
+pub enum ConfigOp {
+    /// Print the value of a config key
+    Get { key: String },
+    /// Set a config key to a value, written in RON like the config files
+    Set { key: String, value: String },
+    /// Print the config the running daemon uses, as JSON
+    Dump,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, clap::Subcommand)]
 pub enum QuakeAction {
     /// Toggle the quake terminal visibility
-    Toggle,
+    Toggle {
+        /// Extra arguments from `arg_profiles` for a terminal this spawns
+        #[arg(long)]
+        arg_profile: Option<String>,
+    },
     /// Open the settings window
     Settings,
+    /// Switch to the next known terminal and respawn it
+    NextTerminal,
+    /// Log the dropdown geometry for the focused output
+    ShowGeometry,
+    /// Switch back to the previously selected terminal
+    SwapTerminal,
+    /// Ignore toggles until re-enabled
+    Disable,
+    /// Respond to toggles again
+    Enable,
+    /// Stop the daemon; the terminal keeps running
+    Quit,
+    /// Show the terminal, e.g. on key press for hold-to-show
+    Show,
+    /// Hide the terminal, e.g. on key release for hold-to-show
+    Hide,
+    /// Toggle, showing the terminal on the focused output
+    ToggleHere,
+    /// Forget the tracked window and find the terminal's window again
+    ResetTracking,
 }
 
 impl std::fmt::Display for QuakeAction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            QuakeAction::Toggle => write!(f, "Toggle"),
+            QuakeAction::Toggle { arg_profile: None } => write!(f, "Toggle"),
+            QuakeAction::Toggle {
+                arg_profile: Some(profile),
+            } => write!(f, "Toggle:{profile}"),
             QuakeAction::Settings => write!(f, "Settings"),
+            QuakeAction::NextTerminal => write!(f, "NextTerminal"),
+            QuakeAction::ShowGeometry => write!(f, "ShowGeometry"),
+            QuakeAction::SwapTerminal => write!(f, "SwapTerminal"),
+            QuakeAction::Disable => write!(f, "Disable"),
+            QuakeAction::Enable => write!(f, "Enable"),
+            QuakeAction::Quit => write!(f, "Quit"),
+            QuakeAction::Show => write!(f, "Show"),
+            QuakeAction::Hide => write!(f, "Hide"),
+            QuakeAction::ToggleHere => write!(f, "ToggleHere"),
+            QuakeAction::ResetTracking => write!(f, "ResetTracking"),
         }
     }
 }
@@ -60,9 +149,46 @@ impl std::str::FromStr for QuakeAction {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
-            "Toggle" => Ok(QuakeAction::Toggle),
+            "Toggle" => Ok(QuakeAction::Toggle { arg_profile: None }),
             "Settings" => Ok(QuakeAction::Settings),
-            other => Err(format!("Unknown action: {other}")),
+            "NextTerminal" => Ok(QuakeAction::NextTerminal),
+            "ShowGeometry" => Ok(QuakeAction::ShowGeometry),
+            "SwapTerminal" => Ok(QuakeAction::SwapTerminal),
+            "Disable" => Ok(QuakeAction::Disable),
+            "Enable" => Ok(QuakeAction::Enable),
+            "Quit" => Ok(QuakeAction::Quit),
+            "Show" => Ok(QuakeAction::Show),
+            "Hide" => Ok(QuakeAction::Hide),
+            "ToggleHere" => Ok(QuakeAction::ToggleHere),
+            "ResetTracking" => Ok(QuakeAction::ResetTracking),
+            other => match other.split_once(':') {
+                Some(("Toggle", profile)) => Ok(QuakeAction::Toggle {
+                    arg_profile: Some(profile.to_string()),
+                }),
+                _ => Err(format!("Unknown action: {other}")),
+            },
+        }
+    }
+}
+
+impl From<QuakeAction> for Message {
+    fn from(action: QuakeAction) -> Self {
+        match action {
+            QuakeAction::Toggle { arg_profile: None } => Message::Toggle,
+            QuakeAction::Toggle {
+                arg_profile: Some(profile),
+            } => Message::ToggleWithProfile(profile),
+            QuakeAction::Settings => Message::OpenSettings,
+            QuakeAction::NextTerminal => Message::NextTerminal,
+            QuakeAction::ShowGeometry => Message::ShowGeometry,
+            QuakeAction::SwapTerminal => Message::SwapTerminal,
+            QuakeAction::Disable => Message::SetEnabled(false),
+            QuakeAction::Enable => Message::SetEnabled(true),
+            QuakeAction::Quit => Message::Quit,
+            QuakeAction::Show => Message::Show,
+            QuakeAction::Hide => Message::Hide,
+            QuakeAction::ToggleHere => Message::ToggleHere,
+            QuakeAction::ResetTracking => Message::ResetTracking,
         }
     }
 }
@@ -72,7 +198,10 @@ impl cosmic::app::CosmicFlags for Args {
     type Args = Vec<String>;
 
     fn action(&self) -> Option<&QuakeAction> {
-        self.subcommand.as_ref()
+        match self.subcommand {
+            Some(CliCommand::Action(ref action)) => Some(action),
+            _ => None,
+        }
     }
 }
 
@@ -87,15 +216,50 @@ enum ToggleState {
 #[derive(Debug, Clone)]
 pub enum Message {
     Toggle,
+    /// Toggle, adding the named `arg_profiles` entry if this spawns
+    ToggleWithProfile(String),
+    ToggleHere,
+    Show,
+    Hide,
+    NextTerminal,
+    ShowGeometry,
+    SwapTerminal,
+    SetEnabled(bool),
+    OpenPath(PathBuf),
+    /// The pointer entered the edge surface
+    EdgePushed,
     ToplevelEvent(ToplevelEvent),
     TerminalExited,
+    /// Reap the untracked terminal processes that have exited
+    Reap,
+    ScanForWindow,
+    /// `post_found_delay_ms` passed since the spawned window was found
+    WindowReady,
+    /// Restart the Wayland loop after its connection dropped
+    Reconnect,
+    /// A background opacity change finished, and whether it succeeded
+    OpacitySet {
+        dimmed: bool,
+        ok: bool,
+    },
     ConfigChanged(QuakeConfig),
     OpenSettings,
     WindowOpened(window::Id),
     WindowClosed(window::Id),
     CloseWindow(window::Id),
+    WindowFocused(window::Id, bool),
+    WindowResized(window::Id, cosmic::iced::Size),
     SetTerminalCommand(usize),
     SetTerminalArgs(String),
+    SetLaunchPrefix(String),
+    ResetTracking,
+    SetTheme(String),
+    SetWindowTitle(String),
+    SetShell(String),
+    SetMonitor(usize),
+    SetExcludeRegion(String),
+    ResetTerminalOnly,
+    Quit,
 }
 
 pub struct QuakeTerminal {
@@ -103,12 +267,83 @@ pub struct QuakeTerminal {
     config: QuakeConfig,
     config_handler: Option<cosmic_config::Config>,
     state: ToggleState,
+    /// Runtime-only switch to ignore toggles, e.g. during a presentation
+    enabled: bool,
     focused: bool,
     refocusing: bool,
+    /// Shown with `reactivate_on_show`, waiting for the compositor to
+    /// report the window activated
+    reactivate_pending: bool,
+    /// Visibility our last show or hide asked for, and when, until the
+    /// compositor confirms it
+    in_flight: Option<(bool, Instant)>,
+    pending_respawn: bool,
     terminal_pid: Option<Arc<AtomicU32>>,
-    terminal_app_id: String,
+    terminal_start_time: Option<u64>,
+    /// Terminal processes no longer tracked that hadn't exited yet
+    unreaped: Vec<u32>,
+    terminal_app_ids: Vec<String>,
+    /// Whether the configured terminal is started with a title flag
+    title_flag: bool,
+    /// Rescans that missed the window since the last spawn
+    window_scans: u32,
+    /// Unique title the terminal was started with, to find its window by
+    /// once the scans time out
+    title_token: Option<String>,
+    /// App_id of the adopted window
+    window_app_id: Option<String>,
+    /// The post-show hook runs once the compositor confirms the show
+    post_show_pending: bool,
+    /// The terminal's background is dimmed to `unfocused_opacity_percent`,
+    /// as far as the last successful opacity change says
+    opacity_dimmed: bool,
+    /// Whether the background should be dimmed
+    opacity_wanted: bool,
+    /// An opacity change is running; the next one waits for it
+    opacity_pending: bool,
+    /// Hide the spawned terminal's window when it appears instead of
+    /// showing it: set from `spawn_behavior` at spawn, then changed by
+    /// actions arriving while the window is missing
+    hide_when_found: bool,
+    /// Set while handling `ToggleHere`
+    show_here: bool,
+    /// Set while handling a toggle with an argument profile
+    arg_profile: Option<String>,
     wayland_controller: Option<WaylandController>,
+    stats: Arc<Stats>,
+    /// Bumped to restart the Wayland loop after it panicked
+    wayland_generation: u32,
+    /// Restarts after a panic since the loop last connected
+    panic_restarts: u32,
+    /// Reconnect attempts since the loop last connected
+    reconnects: u32,
+    /// The Wayland loop was restarted after its connection dropped and
+    /// has not found the terminal window again yet
+    reconnecting: bool,
+    /// Outputs reported by the Wayland loop, kept current across mode and
+    /// scale changes
+    outputs: Vec<OutputInfo>,
+    /// Output of the focused window, as last reported by the Wayland loop
+    focused_output: Option<String>,
+    /// Workspaces reported by the Wayland loop
+    workspaces: Vec<WorkspaceInfo>,
+    /// Open toplevels, listed in the settings window for troubleshooting
+    toplevels: Vec<ToplevelSummary>,
+    /// Entries of the settings window's monitor dropdown and their labels
+    monitors: Vec<Monitor>,
+    monitor_labels: Vec<String>,
+    /// Text of the exclude region input, kept while it doesn't parse
+    exclude_region_text: String,
     settings_window_id: Option<window::Id>,
+    opening_settings: bool,
+    settings_focused: bool,
+    /// Current width of the settings window, for the compact layout
+    settings_width: f32,
+    /// Thin layer surface along the configured edge for `edge_toggle`
+    edge_surface: Option<(window::Id, Position)>,
+    /// A toggle found the configured terminal not installed; the settings
+    /// window asks to pick another one
+    terminal_missing: bool,
 }
 
 impl Application for QuakeTerminal {
@@ -120,35 +355,49 @@ impl Application for QuakeTerminal {
 
     fn init(core: Core, flags: Self::Flags) -> (Self, Task<Self::Message>) {
         let config_handler = cosmic_config::Config::new(APP_ID, CONFIG_VERSION).ok();
-        let config = config_handler
+        let mut config = config_handler
             .as_ref()
             .and_then(|h| QuakeConfig::get_entry(h).ok())
             .unwrap_or_default();
+        config.validate_and_fix();
 
-        // Pre-compute the app_id for the configured terminal
-        let terminal_app_id = process::get_app_id(&config.terminal_command);
+        // First run: prefer the terminal the user already has as default
+        if let Some(ref handler) = config_handler {
+            if handler.get::<String>("terminal_command").is_err() {
+                if let Some(command) = process::detect_default_terminal() {
+                    let _ = config.set_terminal_command(handler, command);
+                }
+            }
+        }
 
-        let app = Self {
-            core,
-            config,
-            config_handler,
-            state: ToggleState::Idle,
-            focused: false,
-            refocusing: false,
-            terminal_pid: None,
-            terminal_app_id,
-            wayland_controller: None,
-            settings_window_id: None,
-        };
+        config.apply_env_overrides();
+
+        let mut app = Self::new(core, config, config_handler);
+        app.stats.set_config(&app.config);
+
+        // Read it ahead of the first spawn, which would otherwise wait for
+        // the login shell
+        if app.config.inherit_login_env {
+            std::thread::spawn(process::login_env);
+        }
+
+        // An initial action decides itself whether to spawn and show
+        if app.config.prewarm && flags.subcommand.is_none() {
+            tracing::info!("Prewarming terminal");
+            app.spawn(None);
+            app.hide_when_found = !app.config.show_on_startup;
+        }
 
         // Dispatch the initial action from CLI flags (first-instance case)
         let task = match flags.subcommand {
-            Some(QuakeAction::Settings) => cosmic::task::message(Message::OpenSettings),
-            Some(QuakeAction::Toggle) => cosmic::task::message(Message::Toggle),
-            None => Task::none(),
+            Some(CliCommand::Action(action)) => cosmic::task::message(Message::from(action)),
+            _ => Task::none(),
         };
 
-        (app, task)
+        app.update_monitors();
+        let edge_task = app.sync_edge_surface();
+
+        (app, Task::batch([task, edge_task]))
     }
 
     fn core(&self) -> &Core {
@@ -161,35 +410,146 @@ impl Application for QuakeTerminal {
 
     fn update(&mut self, message: Self::Message) -> Task<Self::Message> {
         match message {
-            Message::Toggle => self.handle_toggle(),
-            Message::ToplevelEvent(event) => self.handle_toplevel_event(event),
+            Message::Toggle => return self.toggle(),
+            Message::ToggleWithProfile(profile) => {
+                self.arg_profile = Some(profile);
+                let task = self.toggle();
+                self.arg_profile = None;
+                return task;
+            }
+            Message::ToggleHere => {
+                self.show_here = true;
+                let task = self.toggle();
+                self.show_here = false;
+                return task;
+            }
+            Message::Show => {
+                if let Some(task) = self.missing_terminal() {
+                    return task;
+                }
+                self.handle_show();
+            }
+            Message::Hide => self.handle_hide(),
+            Message::NextTerminal => self.next_terminal(),
+            Message::SwapTerminal => self.swap_terminal(),
+            Message::OpenPath(path) => self.open_path(&path),
+            Message::SetEnabled(enabled) => {
+                tracing::info!("Toggling {}", if enabled { "enabled" } else { "disabled" });
+                self.enabled = enabled;
+            }
+            Message::ResetTracking => {
+                if let Some(ref controller) = self.wayland_controller {
+                    controller.reset_tracking();
+                }
+            }
+            Message::ShowGeometry => {
+                if let Some(ref controller) = self.wayland_controller {
+                    controller.report_focused_output();
+                }
+            }
+            Message::ToplevelEvent(event) => return self.handle_toplevel_event(event),
+            Message::OpacitySet { dimmed, ok } => {
+                self.opacity_pending = false;
+                if !ok {
+                    tracing::warn!("Failed to change the terminal's background opacity");
+                    return Task::none();
+                }
+                self.opacity_dimmed = dimmed;
+                return self.sync_opacity();
+            }
+            Message::Reconnect => {
+                self.reconnects += 1;
+                self.wayland_generation += 1;
+                self.stats
+                    .controller_restarts
+                    .fetch_add(1, Ordering::Relaxed);
+                self.reconnecting = self.terminal_pid.is_some();
+                tracing::info!(
+                    "Reconnecting to the compositor ({}/{MAX_WAYLAND_RESTARTS})",
+                    self.reconnects
+                );
+            }
+            Message::WindowReady => {
+                // The window may have closed in the meantime
+                if self.state == ToggleState::WaitingForWindow && self.window_app_id.is_some() {
+                    self.window_ready();
+                }
+            }
+            Message::ScanForWindow => {
+                if self.state == ToggleState::WaitingForWindow {
+                    if let Some(ref controller) = self.wayland_controller {
+                        controller.rescan(self.terminal_app_ids.clone());
+                    }
+                }
+            }
             Message::TerminalExited => {
                 // Only reap the zombie process — do NOT reset state.
                 // Many terminals fork (parent exits, child keeps running),
                 // so PID death does not mean the window is gone.
                 // State is driven by ToplevelEvent::Closed instead.
                 tracing::info!("Terminal process exited (reaping zombie)");
-                if let Some(pid) = self.terminal_pid.take() {
-                    let raw = pid.load(Ordering::Relaxed) as i32;
-                    let _ = nix::sys::wait::waitpid(
-                        nix::unistd::Pid::from_raw(raw),
-                        Some(nix::sys::wait::WaitPidFlag::WNOHANG),
-                    );
+                self.terminal_start_time = None;
+                if let Some(pid) = self.clear_terminal_pid() {
+                    self.reap_or_defer(pid);
                 }
             }
-            Message::ConfigChanged(config) => {
+            Message::Reap => {
+                self.unreaped.retain(|&pid| !process::try_reap(pid));
+            }
+            Message::ConfigChanged(mut config) => {
                 tracing::info!("Config changed");
-                self.terminal_app_id = process::get_app_id(&config.terminal_command);
+                config.validate_and_fix();
+                config.apply_env_overrides();
+                self.stats.set_config(&config);
+                if config.terminal_command != self.config.terminal_command {
+                    self.terminal_missing = false;
+                }
+                let app_ids = process::get_app_ids(&config.terminal_command);
+                if app_ids != self.terminal_app_ids {
+                    if let Some(ref controller) = self.wayland_controller {
+                        controller.set_targets(app_ids.clone());
+                    }
+                    self.terminal_app_ids = app_ids;
+                }
+                self.title_flag = process::has_title_flag(&config.terminal_command);
+                let level_changed =
+                    config.settings_always_on_top != self.config.settings_always_on_top;
+                if parse_region_input(&self.exclude_region_text) != Ok(config.exclude_region) {
+                    self.exclude_region_text = config
+                        .exclude_region
+                        .map(|region| region.to_string())
+                        .unwrap_or_default();
+                }
                 self.config = config;
+                self.update_monitors();
+                if let Some(ref controller) = self.wayland_controller {
+                    controller.set_options(self.wayland_options());
+                }
+                let mut tasks = vec![self.sync_edge_surface()];
+                if let Some(id) = self.settings_window_id.filter(|_| level_changed) {
+                    tasks.push(window::change_level(id, self.settings_level()));
+                }
+                return Task::batch(tasks);
+            }
+            Message::EdgePushed => {
+                tracing::info!(
+                    "Pointer pushed against the {} edge",
+                    self.config.toggle_edge
+                );
+                return self.toggle();
             }
             Message::OpenSettings => {
-                if self.settings_window_id.is_some() {
+                // Rapid repeated activations must not open a second window
+                if self.settings_window_id.is_some() || self.opening_settings {
                     return Task::none();
                 }
+                self.opening_settings = true;
+                self.settings_width = SETTINGS_SIZE.width;
                 let settings = window::Settings {
-                    size: cosmic::iced::Size::new(500.0, 450.0),
+                    size: SETTINGS_SIZE,
                     resizable: true,
                     decorations: false,
+                    level: self.settings_level(),
                     ..window::Settings::default()
                 };
                 let (id, task) = window::open(settings);
@@ -197,23 +557,51 @@ impl Application for QuakeTerminal {
                 let title = fl!("settings-title");
                 return task.discard().chain(self.set_window_title(title, id));
             }
-            Message::WindowOpened(_id) => {}
+            Message::WindowOpened(id) => {
+                if self.settings_window_id == Some(id) {
+                    self.opening_settings = false;
+                }
+            }
             Message::CloseWindow(id) => {
                 if self.settings_window_id == Some(id) {
                     self.settings_window_id = None;
+                    self.opening_settings = false;
+                    self.settings_focused = false;
                     return window::close(id);
                 }
             }
+            Message::WindowFocused(id, focused) => {
+                if self.settings_window_id == Some(id) {
+                    self.settings_focused = focused;
+                }
+            }
+            Message::WindowResized(id, size) => {
+                if self.settings_window_id == Some(id) {
+                    self.settings_width = size.width;
+                }
+            }
             Message::WindowClosed(id) => {
+                // e.g. the compositor dropped the edge surface with its output
+                if self
+                    .edge_surface
+                    .as_ref()
+                    .is_some_and(|(edge_id, _)| *edge_id == id)
+                {
+                    self.edge_surface = None;
+                }
                 if self.settings_window_id == Some(id) {
                     self.settings_window_id = None;
+                    self.opening_settings = false;
+                    self.settings_focused = false;
                 }
             }
             Message::SetTerminalCommand(index) => {
-                if let Some(&(command, _, _)) = KNOWN_TERMINALS.get(index) {
-                    if let Some(ref handler) = self.config_handler {
-                        let _ = self.config.set_terminal_command(handler, command.into());
-                    }
+                let command = self
+                    .terminals()
+                    .get(index)
+                    .map(|&(cmd, _, _)| cmd.to_string());
+                if let Some(command) = command {
+                    self.select_terminal(command);
                 }
             }
             Message::SetTerminalArgs(args_str) => {
@@ -226,6 +614,71 @@ impl Application for QuakeTerminal {
                     let _ = self.config.set_terminal_args(handler, args);
                 }
             }
+            Message::SetLaunchPrefix(prefix) => {
+                let prefix = prefix.split_whitespace().map(String::from).collect();
+                if let Some(ref handler) = self.config_handler {
+                    let _ = self.config.set_launch_prefix(handler, prefix);
+                }
+            }
+            Message::SetTheme(theme) => {
+                let theme = Some(theme.trim().to_string()).filter(|t| !t.is_empty());
+                if let Some(ref handler) = self.config_handler {
+                    let _ = self.config.set_theme(handler, theme);
+                }
+            }
+            Message::SetWindowTitle(title) => {
+                let title = Some(title).filter(|t| !t.trim().is_empty());
+                if let Some(ref handler) = self.config_handler {
+                    let _ = self.config.set_window_title(handler, title);
+                }
+            }
+            Message::SetShell(shell) => {
+                let shell = Some(shell.trim().to_string()).filter(|s| !s.is_empty());
+                if let Some(ref handler) = self.config_handler {
+                    let _ = self.config.set_shell(handler, shell);
+                }
+            }
+            Message::SetExcludeRegion(text) => {
+                let region = parse_region_input(&text);
+                self.exclude_region_text = text;
+                if let (Ok(region), Some(handler)) = (region, self.config_handler.as_ref()) {
+                    let _ = self.config.set_exclude_region(handler, region);
+                }
+            }
+            Message::SetMonitor(index) => {
+                if let Some(monitor) = self.monitors.get(index).cloned() {
+                    if let Some(ref handler) = self.config_handler {
+                        let _ = self.config.set_monitor(handler, monitor);
+                    } else {
+                        self.config.monitor = monitor;
+                    }
+                    self.update_monitors();
+                }
+            }
+            Message::Quit => {
+                tracing::info!("Quitting");
+                // The unit keeps systemd's default kill mode, so a terminal
+                // in the service's cgroup is killed with the daemon; end it
+                // first, the way it is configured. A scope is only used if
+                // systemd-run exists.
+                let scoped = self.config.detach_strategy == DetachStrategy::SystemdScope
+                    && process::is_installed("systemd-run");
+                let in_service = std::env::var_os("INVOCATION_ID").is_some() && !scoped;
+                if in_service && self.config.close_command.is_some() {
+                    self.terminate_terminal(true);
+                }
+                if let Some(controller) = self.wayland_controller.take() {
+                    controller.shutdown();
+                }
+                return cosmic::iced::exit();
+            }
+            Message::ResetTerminalOnly => {
+                tracing::info!("Resetting terminal selection to default");
+                self.select_terminal(QuakeConfig::default().terminal_command);
+                if let Some(ref handler) = self.config_handler {
+                    let _ = self.config.set_terminal_args(handler, Vec::new());
+                }
+            }
         }
         Task::none()
     }
@@ -236,18 +689,45 @@ impl Application for QuakeTerminal {
     }
 
     fn view_window(&self, id: window::Id) -> Element<'_, Self::Message> {
+        if self
+            .edge_surface
+            .as_ref()
+            .is_some_and(|(edge_id, _)| *edge_id == id)
+        {
+            return cosmic::iced::widget::mouse_area(
+                container(text(""))
+                    .class(cosmic::style::Container::Transparent)
+                    .width(Length::Fill)
+                    .height(Length::Fill),
+            )
+            .on_enter(Message::EdgePushed)
+            .into();
+        }
+
         if self.settings_window_id != Some(id) {
             return text("").into();
         }
 
         let terminal_index = self.terminal_index();
+        // Narrow windows get labels above their inputs and less padding
+        let compact = self.settings_width < COMPACT_WIDTH;
+        let (icon_size, spacing, padding) = if compact { (16, 8, 8) } else { (24, 12, 24) };
 
         let mut terminal_section = settings::section().title(fl!("settings-terminal"));
 
-        for (i, &(_, display_name, icon_name)) in KNOWN_TERMINALS.iter().enumerate() {
-            let icon = widget::icon::from_name(icon_name).size(24).prefer_svg(true);
+        if self.terminal_missing {
+            terminal_section = terminal_section.add(text(fl!(
+                "terminal-missing",
+                command = self.config.terminal_command.as_str()
+            )));
+        }
+
+        for (i, (_, display_name, icon_name)) in self.terminals().into_iter().enumerate() {
+            let icon = widget::icon::from_name(icon_name)
+                .size(icon_size)
+                .prefer_svg(true);
             let label = widget::row::with_children(vec![icon.into(), text(display_name).into()])
-                .spacing(12)
+                .spacing(spacing)
                 .align_y(Alignment::Center);
 
             terminal_section = terminal_section.add(widget::radio(
@@ -258,7 +738,8 @@ impl Application for QuakeTerminal {
             ));
         }
 
-        let terminal_section = terminal_section.add(settings::item(
+        let terminal_section = terminal_section.add(labeled_item(
+            compact,
             fl!("terminal-args"),
             text_input(
                 fl!("terminal-args-placeholder"),
@@ -267,7 +748,88 @@ impl Application for QuakeTerminal {
             .on_input(Message::SetTerminalArgs),
         ));
 
-        let content = settings::view_column(vec![terminal_section.into()]).padding([0, 24]);
+        let terminal_section = terminal_section.add(labeled_item(
+            compact,
+            fl!("launch-prefix"),
+            text_input(
+                fl!("launch-prefix-placeholder"),
+                self.config.launch_prefix.join(" "),
+            )
+            .on_input(Message::SetLaunchPrefix),
+        ));
+
+        let terminal_section = terminal_section.add(labeled_item(
+            compact,
+            fl!("terminal-theme"),
+            text_input(
+                fl!("terminal-theme-placeholder"),
+                self.config.theme.as_deref().unwrap_or_default(),
+            )
+            .on_input(Message::SetTheme),
+        ));
+
+        let terminal_section = terminal_section.add(labeled_item(
+            compact,
+            fl!("window-title"),
+            text_input(
+                fl!("window-title-placeholder"),
+                self.config.window_title.as_deref().unwrap_or_default(),
+            )
+            .on_input(Message::SetWindowTitle),
+        ));
+
+        let terminal_section = terminal_section.add(labeled_item(
+            compact,
+            fl!("shell"),
+            text_input(
+                fl!("shell-placeholder"),
+                self.config.shell.as_deref().unwrap_or_default(),
+            )
+            .on_input(Message::SetShell),
+        ));
+
+        let terminal_section = terminal_section.add(labeled_item(
+            compact,
+            fl!("monitor"),
+            widget::dropdown(
+                &self.monitor_labels,
+                self.monitors.iter().position(|m| *m == self.config.monitor),
+                Message::SetMonitor,
+            ),
+        ));
+
+        let terminal_section = terminal_section.add(labeled_item(
+            compact,
+            fl!("exclude-region"),
+            text_input(
+                fl!("exclude-region-placeholder"),
+                self.exclude_region_text.as_str(),
+            )
+            .on_input(Message::SetExcludeRegion),
+        ));
+
+        let terminal_section =
+            terminal_section.add(settings::item_row(vec![widget::button::standard(fl!(
+                "reset-terminal"
+            ))
+            .on_press(Message::ResetTerminalOnly)
+            .into()]));
+
+        // Lets users of unlisted terminals find the app_id to match
+        let mut diagnostics_section = settings::section().title(fl!("settings-diagnostics"));
+        for toplevel in &self.toplevels {
+            let title = if toplevel.ours {
+                fl!("diagnostics-ours", title = toplevel.title.as_str())
+            } else {
+                toplevel.title.clone()
+            };
+            diagnostics_section =
+                diagnostics_section.add(settings::item(toplevel.app_id.as_str(), text(title)));
+        }
+
+        let content =
+            settings::view_column(vec![terminal_section.into(), diagnostics_section.into()])
+                .padding([0, padding]);
 
         let header = header_bar()
             .title(fl!("settings-title"))
@@ -281,33 +843,59 @@ impl Application for QuakeTerminal {
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        let mut subs = vec![wayland::toplevel_subscription(self.terminal_app_id.clone())
-            .map(Message::ToplevelEvent)];
+        let mut subs = vec![wayland::toplevel_subscription(
+            self.terminal_app_ids.clone(),
+            self.wayland_options(),
+            self.wayland_generation,
+        )
+        .map(Message::ToplevelEvent)];
 
-        // Monitor terminal process exit via kill(pid, 0)
+        // Monitor terminal process exit via kill(pid, 0) and its start time.
+        // Keyed by PID so a respawn replaces the previous monitor.
         if let Some(ref pid_holder) = self.terminal_pid {
             let pid_holder = pid_holder.clone();
+            let start_time = self.terminal_start_time;
             subs.push(cosmic::iced::Subscription::run_with_id(
-                "process-monitor",
-                futures::stream::unfold(pid_holder, |pid_holder| async move {
+                ("process-monitor", pid_holder.load(Ordering::Relaxed)),
+                futures::stream::unfold(pid_holder, move |pid_holder| async move {
                     loop {
                         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
                         let pid = pid_holder.load(Ordering::Relaxed);
-                        if pid != 0 {
-                            let alive = nix::sys::signal::kill(
-                                nix::unistd::Pid::from_raw(pid as i32),
-                                None,
-                            )
-                            .is_ok();
-                            if !alive {
-                                return Some((Message::TerminalExited, pid_holder));
-                            }
+                        // The app stopped tracking this process
+                        if pid == 0 {
+                            return None;
+                        }
+                        if !process::is_alive(pid, start_time) {
+                            return Some((Message::TerminalExited, pid_holder));
                         }
                     }
                 }),
             ));
         }
 
+        // Keep reaping terminals that outlived our tracking, so they don't
+        // linger as zombies once they exit
+        if !self.unreaped.is_empty() {
+            subs.push(cosmic::iced::Subscription::run_with_id(
+                "reaper",
+                futures::stream::unfold((), |()| async {
+                    tokio::time::sleep(REAP_INTERVAL).await;
+                    Some((Message::Reap, ()))
+                }),
+            ));
+        }
+
+        // Rescan the toplevels while the spawned terminal's window is missing
+        if self.state == ToggleState::WaitingForWindow {
+            subs.push(cosmic::iced::Subscription::run_with_id(
+                "window-scan",
+                futures::stream::unfold((), |()| async {
+                    tokio::time::sleep(WINDOW_SCAN_INTERVAL).await;
+                    Some((Message::ScanForWindow, ()))
+                }),
+            ));
+        }
+
         // Watch for config changes
         if self.config_handler.is_some() {
             subs.push(
@@ -325,11 +913,17 @@ impl Application for QuakeTerminal {
             );
         }
 
+        subs.push(stats::subscription(self.stats.clone()));
+        subs.push(signal_subscription());
+
         // Watch for window events (settings window open/close)
         subs.push(event::listen_with(|event, _status, id| match event {
             Event::Window(window::Event::CloseRequested) => Some(Message::CloseWindow(id)),
             Event::Window(window::Event::Opened { .. }) => Some(Message::WindowOpened(id)),
             Event::Window(window::Event::Closed) => Some(Message::WindowClosed(id)),
+            Event::Window(window::Event::Focused) => Some(Message::WindowFocused(id, true)),
+            Event::Window(window::Event::Unfocused) => Some(Message::WindowFocused(id, false)),
+            Event::Window(window::Event::Resized(size)) => Some(Message::WindowResized(id, size)),
             _ => None,
         }));
 
@@ -345,58 +939,524 @@ impl Application for QuakeTerminal {
             }
             Details::ActivateAction { action, .. } => {
                 if let Ok(cmd) = action.parse::<QuakeAction>() {
-                    match cmd {
-                        QuakeAction::Toggle => {
-                            return cosmic::task::message(Message::Toggle);
-                        }
-                        QuakeAction::Settings => {
-                            return cosmic::task::message(Message::OpenSettings);
-                        }
-                    }
+                    return cosmic::task::message(Message::from(cmd));
+                }
+            }
+            Details::Open { url } => {
+                if let Some(path) = url.iter().find_map(|url| url.to_file_path().ok()) {
+                    return cosmic::task::message(Message::OpenPath(path));
                 }
             }
-            Details::Open { .. } => {}
         }
         Task::none()
     }
 }
 
 impl QuakeTerminal {
+    fn new(core: Core, config: QuakeConfig, config_handler: Option<cosmic_config::Config>) -> Self {
+        // Pre-compute the app_ids for the configured terminal
+        let terminal_app_ids = process::get_app_ids(&config.terminal_command);
+        let title_flag = process::has_title_flag(&config.terminal_command);
+        let exclude_region_text = config
+            .exclude_region
+            .map(|region| region.to_string())
+            .unwrap_or_default();
+
+        Self {
+            core,
+            config,
+            config_handler,
+            state: ToggleState::Idle,
+            enabled: true,
+            focused: false,
+            refocusing: false,
+            reactivate_pending: false,
+            in_flight: None,
+            pending_respawn: false,
+            terminal_pid: None,
+            terminal_start_time: None,
+            unreaped: Vec::new(),
+            terminal_app_ids,
+            title_flag,
+            window_scans: 0,
+            title_token: None,
+            window_app_id: None,
+            post_show_pending: false,
+            opacity_dimmed: false,
+            opacity_wanted: false,
+            opacity_pending: false,
+            hide_when_found: false,
+            show_here: false,
+            arg_profile: None,
+            wayland_controller: None,
+            stats: Arc::new(Stats::default()),
+            wayland_generation: 0,
+            panic_restarts: 0,
+            reconnects: 0,
+            reconnecting: false,
+            outputs: Vec::new(),
+            focused_output: None,
+            workspaces: Vec::new(),
+            toplevels: Vec::new(),
+            monitors: Vec::new(),
+            monitor_labels: Vec::new(),
+            exclude_region_text,
+            settings_window_id: None,
+            opening_settings: false,
+            settings_focused: false,
+            settings_width: SETTINGS_SIZE.width,
+            terminal_missing: false,
+            edge_surface: None,
+        }
+    }
+
+    /// Built-in terminals followed by the user's `custom_terminals`.
+    fn terminals(&self) -> Vec<(&str, &str, &str)> {
+        process::KNOWN_TERMINALS
+            .iter()
+            .map(|&(cmd, name, icon, _)| (cmd, name, icon))
+            .chain(
+                self.config
+                    .custom_terminals
+                    .iter()
+                    .map(|(cmd, name, icon)| (cmd.as_str(), name.as_str(), icon.as_str())),
+            )
+            .collect()
+    }
+
     fn terminal_index(&self) -> usize {
-        KNOWN_TERMINALS
+        self.terminals()
             .iter()
             .position(|&(cmd, _, _)| cmd == self.config.terminal_command)
             .unwrap_or(0)
     }
 
+    fn next_terminal(&mut self) {
+        let (command, display_name) = {
+            let terminals = self.terminals();
+            let (command, display_name, _) =
+                terminals[(self.terminal_index() + 1) % terminals.len()];
+            (command.to_string(), display_name.to_string())
+        };
+        tracing::info!("Switching terminal to {display_name} ({command})");
+        self.select_terminal(command);
+        self.respawn_terminal();
+    }
+
+    fn swap_terminal(&mut self) {
+        let previous = self.config.previous_terminal_command.clone();
+        if previous.is_empty() || previous == self.config.terminal_command {
+            tracing::info!("No previous terminal to swap to");
+            return;
+        }
+        tracing::info!("Swapping terminal to {previous}");
+        self.select_terminal(previous);
+        if self.state != ToggleState::Idle {
+            self.respawn_terminal();
+        }
+    }
+
+    /// Persists `command` as the terminal, remembering the current one as
+    /// the previous terminal for `SwapTerminal`.
+    fn select_terminal(&mut self, command: String) {
+        if command == self.config.terminal_command {
+            return;
+        }
+        let previous = self.config.terminal_command.clone();
+        self.terminal_missing = false;
+        self.terminal_app_ids = process::get_app_ids(&command);
+        self.title_flag = process::has_title_flag(&command);
+        if let Some(ref controller) = self.wayland_controller {
+            controller.set_targets(self.terminal_app_ids.clone());
+        }
+        if let Some(ref handler) = self.config_handler {
+            let _ = self.config.set_previous_terminal_command(handler, previous);
+            let _ = self.config.set_terminal_command(handler, command);
+        } else {
+            self.config.previous_terminal_command = previous;
+            self.config.terminal_command = command;
+        }
+    }
+
+    /// Replaces the running terminal (if any) with a fresh spawn of the
+    /// configured one.
+    fn respawn_terminal(&mut self) {
+        match self.state {
+            ToggleState::Idle => self.handle_toggle(),
+            ToggleState::WaitingForWindow => {
+                // No window to close yet — drop the pending process and start over
+                self.terminate_terminal(false);
+                self.state = ToggleState::Idle;
+                self.handle_toggle();
+            }
+            ToggleState::Visible | ToggleState::Hidden => {
+                // Close the current window; the Closed event spawns the replacement
+                self.pending_respawn = true;
+                if self.config.close_command.is_some() {
+                    self.terminate_terminal(false);
+                } else if let Some(ref controller) = self.wayland_controller {
+                    controller.close();
+                }
+            }
+        }
+    }
+
+    /// Creates, moves or removes the edge surface to match the config.
+    fn sync_edge_surface(&mut self) -> Task<Message> {
+        let wanted = self
+            .config
+            .edge_toggle
+            .then(|| self.config.toggle_edge.clone());
+        if self.edge_surface.as_ref().map(|(_, edge)| edge) == wanted.as_ref() {
+            return Task::none();
+        }
+
+        let mut tasks = Vec::new();
+        if let Some((id, _)) = self.edge_surface.take() {
+            tasks.push(destroy_layer_surface(id));
+        }
+        if let Some(edge) = wanted {
+            let id = window::Id::unique();
+            let edge_anchor = match edge {
+                Position::Top => Anchor::TOP,
+                Position::Bottom => Anchor::BOTTOM,
+            };
+            tasks.push(get_layer_surface(SctkLayerSurfaceSettings {
+                id,
+                layer: Layer::Overlay,
+                keyboard_interactivity: KeyboardInteractivity::None,
+                anchor: edge_anchor | Anchor::LEFT | Anchor::RIGHT,
+                // Reach the very edge, above panels that reserve space there
+                exclusive_zone: -1,
+                size: Some((None, Some(1))),
+                namespace: "quake-terminal-edge".into(),
+                output: IcedOutput::Active,
+                ..Default::default()
+            }));
+            self.edge_surface = Some((id, edge));
+        }
+        Task::batch(tasks)
+    }
+
+    fn wayland_options(&self) -> WaylandOptions {
+        WaylandOptions {
+            raise_over_fullscreen: self.config.raise_over_fullscreen,
+            ignore_app_ids: self.config.ignore_app_ids.clone(),
+            monitor: self.config.monitor.clone(),
+        }
+    }
+
+    /// Rebuilds the monitor dropdown from the connected outputs.
+    fn update_monitors(&mut self) {
+        let names: Vec<&str> = self.outputs.iter().map(|o| o.name.as_str()).collect();
+        self.monitors = monitor_choices(&names, &self.config.monitor);
+        self.monitor_labels = self
+            .monitors
+            .iter()
+            .map(|monitor| match monitor {
+                Monitor::Focused => fl!("monitor-focused"),
+                Monitor::Primary => fl!("monitor-primary"),
+                Monitor::Named(name) => name.clone(),
+            })
+            .collect();
+    }
+
+    /// Stops tracking the terminal process, returning its PID. Zeroing the
+    /// shared PID also ends the process monitor holding a clone of it.
+    fn clear_terminal_pid(&mut self) -> Option<u32> {
+        let pid_holder = self.terminal_pid.take()?;
+        Some(pid_holder.swap(0, Ordering::Relaxed)).filter(|&pid| pid != 0)
+    }
+
+    /// Ends the terminal process, with `close_command` if one is set. With
+    /// `wait`, the command's grace period is waited out here rather than in
+    /// the background, for when the daemon is about to exit.
+    fn terminate_terminal(&mut self, wait: bool) {
+        let start_time = self.terminal_start_time.take();
+        let env = self.hook_env();
+        if let Some(pid) = self.clear_terminal_pid() {
+            let nix_pid = nix::unistd::Pid::from_raw(pid as i32);
+            if !process::is_alive(pid, start_time) {
+                tracing::info!("PID {pid} no longer belongs to our terminal, not signaling it");
+            } else if let Some(ref command) = self.config.close_command {
+                tracing::info!(
+                    "Closing PID {pid} with close_command, then SIGTERM after {}s",
+                    CLOSE_GRACE.as_secs()
+                );
+                process::run_hook("close", command, &env);
+                if wait {
+                    process::terminate_within(pid, start_time, CLOSE_GRACE);
+                } else {
+                    process::terminate_after(pid, start_time, CLOSE_GRACE);
+                }
+            } else {
+                tracing::info!("Sending SIGTERM to PID {pid}");
+                let _ = nix::sys::signal::kill(nix_pid, nix::sys::signal::Signal::SIGTERM);
+            }
+            self.reap_or_defer(pid);
+        }
+    }
+
+    /// Reaps a terminal process we stopped tracking, or, while it is still
+    /// running (e.g. handling SIGTERM), leaves it to the periodic reaper.
+    fn reap_or_defer(&mut self, pid: u32) {
+        if !process::try_reap(pid) {
+            tracing::debug!("PID {pid} is still running, reaping it later");
+            self.unreaped.push(pid);
+        }
+    }
+
+    /// Gives up on a terminal whose window never appeared, so the next
+    /// toggle spawns a fresh one.
+    fn spawn_timed_out(&mut self) {
+        tracing::error!(
+            "Spawn timed out: no window with app_id {:?} appeared; \
+             the app_ids of the open windows are logged above",
+            self.terminal_app_ids
+        );
+        self.state = ToggleState::Idle;
+        self.terminate_terminal(false);
+    }
+
+    fn spawn(&mut self, cwd: Option<&Path>) {
+        let mut args = self.config.terminal_args.clone();
+        if let Some(ref name) = self.arg_profile {
+            match self.config.arg_profiles.get(name) {
+                Some(profile) => {
+                    tracing::info!("Using argument profile {name}: {profile:?}");
+                    args.extend(profile.iter().cloned());
+                }
+                None => {
+                    tracing::warn!("No argument profile named {name}, using the base arguments")
+                }
+            }
+        }
+        // Without a title of its own, a terminal with a title flag is
+        // started with a unique one, in case it ignores the class flag
+        let title_token =
+            (self.config.window_title.is_none() && self.title_flag).then(process::title_token);
+        if let Some(ref controller) = self.wayland_controller {
+            controller.watch_title(title_token.clone());
+        }
+        let options = process::SpawnOptions {
+            args: &args,
+            launch_prefix: &self.config.launch_prefix,
+            cwd,
+            theme: self.config.theme.as_deref(),
+            detach: self.config.detach_strategy.clone(),
+            startup_commands: &self.config.startup_commands,
+            size: self.spawn_size(),
+            title: self
+                .config
+                .window_title
+                .as_deref()
+                .or(title_token.as_deref()),
+            shell: self.config.shell.as_deref(),
+            tmux_session: self.config.tmux_session.as_deref(),
+            login_env: self.config.inherit_login_env,
+            remote_control: self.config.open_in_tab
+                || self.config.unfocused_opacity_percent.is_some(),
+        };
+        if options.tmux_session.is_some() && !process::is_installed("tmux") {
+            tracing::warn!("tmux_session is set, but tmux is not installed");
+        }
+        let result = process::spawn_terminal(&self.config.terminal_command, &options);
+        let counter = if result.is_some() {
+            &self.stats.spawns
+        } else {
+            &self.stats.spawn_failures
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        if let Some(result) = result {
+            self.spawned(result);
+            self.title_token = title_token;
+        }
+    }
+
+    /// Dropdown size for `size_flags`, on the output `monitor` selects.
+    fn spawn_size(&self) -> Option<(i32, i32)> {
+        if !self.config.size_flags {
+            return None;
+        }
+        let Some(output) = size_output(
+            &self.config.monitor,
+            &self.outputs,
+            self.focused_output.as_deref(),
+        ) else {
+            tracing::info!("Target output not known yet, spawning without size flags");
+            return None;
+        };
+        let rect = geometry::compute_rect(output.rect, &self.config);
+        Some((rect.width, rect.height))
+    }
+
+    /// Stacking level requested for the settings window.
+    fn settings_level(&self) -> window::Level {
+        if self.config.settings_always_on_top {
+            window::Level::AlwaysOnTop
+        } else {
+            window::Level::Normal
+        }
+    }
+
+    /// Dims the terminal's background to `unfocused_opacity_percent`, or
+    /// restores it to fully opaque.
+    fn set_opacity_dimmed(&mut self, dimmed: bool) -> Task<Message> {
+        self.opacity_wanted = dimmed;
+        self.sync_opacity()
+    }
+
+    /// Starts an opacity change if the background isn't as wanted and no
+    /// change is running. Changes set absolute values, so one that failed
+    /// is simply made again on the next focus change.
+    fn sync_opacity(&mut self) -> Task<Message> {
+        let Some(unfocused) = self.config.unfocused_opacity_percent else {
+            return Task::none();
+        };
+        if self.opacity_pending || self.opacity_wanted == self.opacity_dimmed {
+            return Task::none();
+        }
+        let dimmed = self.opacity_wanted;
+        let percent = if dimmed { unfocused } else { 100 };
+        let Some(mut cmd) =
+            process::background_opacity_command(&self.config.terminal_command, percent)
+        else {
+            return Task::none();
+        };
+        self.opacity_pending = true;
+        cosmic::task::future(async move {
+            let ok = tokio::task::spawn_blocking(move || {
+                cmd.status().is_ok_and(|status| status.success())
+            })
+            .await
+            .unwrap_or(false);
+            Message::OpacitySet { dimmed, ok }
+        })
+    }
+
+    /// Starts waiting for the window of a freshly spawned terminal.
+    fn spawned(&mut self, result: process::SpawnResult) {
+        if let Some(pid) = self.clear_terminal_pid() {
+            self.reap_or_defer(pid);
+        }
+        self.terminal_pid = Some(Arc::new(AtomicU32::new(result.pid)));
+        self.terminal_start_time = result.start_time;
+        self.terminal_app_ids = result.app_ids;
+        if let Some(ref controller) = self.wayland_controller {
+            controller.set_targets(self.terminal_app_ids.clone());
+        }
+        self.opacity_dimmed = false;
+        self.opacity_wanted = false;
+        if self.config.unfocused_opacity_percent.is_some()
+            && !process::supports_background_opacity(&self.config.terminal_command)
+        {
+            tracing::warn!(
+                "unfocused_opacity_percent is set, but {} has no runtime opacity control",
+                self.config.terminal_command
+            );
+        }
+        self.window_scans = 0;
+        self.hide_when_found = self.config.spawn_behavior == SpawnBehavior::Hidden;
+        self.state = ToggleState::WaitingForWindow;
+    }
+
+    /// Shows the terminal in `path`: a fresh terminal is spawned there, a
+    /// running one gets a new tab if it supports it and is brought forward.
+    fn open_path(&mut self, path: &Path) {
+        match self.state {
+            ToggleState::Idle => {
+                tracing::info!("Spawning terminal in {}", path.display());
+                self.spawn(Some(path));
+            }
+            ToggleState::WaitingForWindow => {
+                tracing::debug!("Open path: still waiting for window to appear");
+            }
+            ToggleState::Visible | ToggleState::Hidden => {
+                if !self.config.open_in_tab {
+                    tracing::info!("open_in_tab is off, just showing the terminal");
+                } else if !process::open_tab(&self.config.terminal_command, path) {
+                    tracing::info!(
+                        "{} can't open tabs remotely, just showing it",
+                        self.config.terminal_command
+                    );
+                }
+                if let Some(ref controller) = self.wayland_controller {
+                    controller.activate();
+                }
+                self.state = ToggleState::Visible;
+                self.focused = true;
+            }
+        }
+    }
+
+    /// A user-initiated toggle, which may also close the settings window.
+    fn toggle(&mut self) -> Task<Message> {
+        if let Some(task) = self.missing_terminal() {
+            return task;
+        }
+        self.handle_toggle();
+        match self.settings_window_id {
+            Some(id) if self.config.close_settings_on_toggle && self.enabled => {
+                tracing::info!("Toggle: closing settings window");
+                cosmic::task::message(Message::CloseWindow(id))
+            }
+            _ => Task::none(),
+        }
+    }
+
+    /// Opens the settings window instead of spawning a terminal that isn't
+    /// installed, so the first toggle on a fresh setup isn't silently lost.
+    fn missing_terminal(&mut self) -> Option<Task<Message>> {
+        if !self.enabled
+            || self.state != ToggleState::Idle
+            || process::is_usable(&self.config.terminal_command)
+        {
+            return None;
+        }
+        tracing::warn!(
+            "{} is not installed, opening settings to pick a terminal",
+            self.config.terminal_command
+        );
+        self.terminal_missing = true;
+        Some(cosmic::task::message(Message::OpenSettings))
+    }
+
     fn handle_toggle(&mut self) {
+        if !self.enabled {
+            tracing::info!("Toggle ignored: quake terminal is disabled");
+            return;
+        }
+        self.stats.toggles.fetch_add(1, Ordering::Relaxed);
+
         match self.state {
             ToggleState::Idle => {
                 tracing::info!("Toggle: spawning terminal");
-                let result = process::spawn_terminal(
-                    &self.config.terminal_command,
-                    &self.config.terminal_args,
-                );
-                if let Some(result) = result {
-                    let pid = result.pid;
-                    self.terminal_pid = Some(Arc::new(AtomicU32::new(pid)));
-                    self.terminal_app_id = result.app_id;
-                    self.state = ToggleState::WaitingForWindow;
-                }
+                self.spawn(None);
             }
             ToggleState::WaitingForWindow => {
-                tracing::debug!("Toggle: still waiting for window to appear");
+                // A press while the terminal starts is never lost: with
+                // `Hidden` it is the second press that shows the terminal
+                if self.hide_when_found {
+                    tracing::info!("Toggle: showing terminal once its window appears");
+                    self.hide_when_found = false;
+                } else {
+                    tracing::debug!("Toggle: still waiting for window to appear");
+                }
             }
             ToggleState::Visible => {
-                if self.focused {
+                // Don't pull the terminal over the settings window while the
+                // user is working in it
+                let hide = self.focused
+                    || self.settings_focused
+                    || self.config.toggle_when_unfocused == UnfocusedToggle::Hide;
+                if hide {
                     tracing::info!("Toggle: hiding terminal");
-                    if let Some(ref controller) = self.wayland_controller {
-                        controller.minimize();
-                    }
-                    self.state = ToggleState::Hidden;
-                    self.focused = false;
+                    self.hide();
                 } else {
                     tracing::info!("Toggle: refocusing terminal (minimize first)");
+                    // The minimize is expected; an earlier show must not
+                    // make it look stale
+                    self.in_flight = None;
                     if let Some(ref controller) = self.wayland_controller {
                         controller.minimize();
                     }
@@ -405,29 +1465,308 @@ impl QuakeTerminal {
             }
             ToggleState::Hidden => {
                 tracing::info!("Toggle: showing terminal");
+                self.show();
+            }
+        }
+    }
+
+    /// Shows the terminal, spawning it if needed. Repeats while it is shown
+    /// or starting do nothing, so key repeat of a held binding is harmless.
+    fn handle_show(&mut self) {
+        if !self.enabled {
+            tracing::info!("Show ignored: quake terminal is disabled");
+            return;
+        }
+
+        match self.state {
+            ToggleState::Idle => {
+                tracing::info!("Show: spawning terminal");
+                self.spawn(None);
+                self.hide_when_found = false;
+            }
+            ToggleState::WaitingForWindow => {
+                // A hide that arrived in the meantime is superseded
+                self.hide_when_found = false;
+            }
+            ToggleState::Visible => {}
+            ToggleState::Hidden => {
+                tracing::info!("Show: showing terminal");
+                self.show();
+            }
+        }
+    }
+
+    /// Hides the terminal if it is shown. A terminal that is still starting
+    /// is hidden as soon as its window appears.
+    fn handle_hide(&mut self) {
+        if !self.enabled {
+            tracing::info!("Hide ignored: quake terminal is disabled");
+            return;
+        }
+
+        match self.state {
+            ToggleState::WaitingForWindow => {
+                tracing::info!("Hide: terminal still starting, hiding it once it appears");
+                self.hide_when_found = true;
+            }
+            ToggleState::Visible => {
+                tracing::info!("Hide: hiding terminal");
+                self.hide();
+            }
+            ToggleState::Idle | ToggleState::Hidden => {}
+        }
+    }
+
+    fn show(&mut self) {
+        if let Some(ref controller) = self.wayland_controller {
+            if self.show_here {
+                controller.activate_here();
+            } else {
+                controller.activate();
+            }
+        }
+        self.post_show_pending = self.config.post_show_command.is_some();
+        self.reactivate_pending = self.config.reactivate_on_show;
+        self.in_flight = Some((true, Instant::now()));
+        self.state = ToggleState::Visible;
+        self.focused = true;
+    }
+
+    /// Environment for hooks describing the terminal window.
+    fn hook_env(&self) -> Vec<(&'static str, String)> {
+        let mut env = Vec::new();
+        if let Some(pid) = self
+            .terminal_pid
+            .as_ref()
+            .map(|pid| pid.load(Ordering::Relaxed))
+        {
+            env.push(("QUAKE_PID", pid.to_string()));
+        }
+        if let Some(ref app_id) = self.window_app_id {
+            env.push(("QUAKE_APP_ID", app_id.clone()));
+        }
+        env
+    }
+
+    fn hide(&mut self) {
+        if let Some(ref command) = self.config.pre_hide_command {
+            process::run_hook("pre-hide", command, &self.hook_env());
+        }
+        self.post_show_pending = false;
+        if let Some(ref controller) = self.wayland_controller {
+            controller.hide(&self.config.hide_strategy);
+        }
+        self.reactivate_pending = false;
+        self.in_flight = Some((false, Instant::now()));
+        self.state = ToggleState::Hidden;
+        self.focused = false;
+    }
+
+    /// Whether the compositor reporting the window `visible` contradicts
+    /// our last show or hide before confirming it. Such a report is about
+    /// an earlier command; trusting it would make the next toggle undo what
+    /// the user just asked for. Once settled, or confirmed, reports are
+    /// trusted again, so manual minimizing and restoring still count.
+    fn stale_report(&mut self, visible: bool) -> bool {
+        match self.in_flight {
+            Some((wanted, at)) if wanted != visible && at.elapsed() < COMMAND_SETTLE => true,
+            in_flight => {
+                if let Some((_, at)) = in_flight.filter(|(wanted, _)| *wanted == visible) {
+                    self.record_latency(visible, at.elapsed());
+                }
+                self.in_flight = None;
+                false
+            }
+        }
+    }
+
+    /// Logs how long the compositor took to confirm a show or hide and
+    /// keeps it for the stats.
+    fn record_latency(&self, visible: bool, latency: std::time::Duration) {
+        let (what, stat) = if visible {
+            ("Show", &self.stats.show_latency_ms)
+        } else {
+            ("Hide", &self.stats.hide_latency_ms)
+        };
+        tracing::info!("{what} confirmed after {} ms", latency.as_millis());
+        stat.store(latency.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Hides or places the freshly found window of a spawn.
+    fn window_ready(&mut self) {
+        if self.hide_when_found {
+            tracing::info!("Hiding freshly spawned terminal until next toggle");
+            if let Some(ref controller) = self.wayland_controller {
+                controller.hide(&self.config.hide_strategy);
+            }
+            self.state = ToggleState::Hidden;
+            self.focused = false;
+        } else {
+            self.state = ToggleState::Visible;
+            self.focused = true;
+            // The compositor opened the window wherever it liked
+            if self.config.monitor != Monitor::Focused {
                 if let Some(ref controller) = self.wayland_controller {
                     controller.activate();
                 }
-                self.state = ToggleState::Visible;
-                self.focused = true;
             }
         }
     }
 
-    fn handle_toplevel_event(&mut self, event: ToplevelEvent) {
+    fn handle_toplevel_event(&mut self, event: ToplevelEvent) -> Task<Message> {
         match event {
             ToplevelEvent::Ready(controller) => {
                 tracing::info!("Wayland toplevel controller ready");
+                controller.set_options(self.wayland_options());
                 self.wayland_controller = Some(controller);
             }
-            ToplevelEvent::Found => {
+            ToplevelEvent::Connected => {
+                self.panic_restarts = 0;
+                self.reconnects = 0;
+            }
+            ToplevelEvent::FocusedOutput(output) => {
+                let Some(output) = output else {
+                    tracing::warn!("No output available to compute geometry for");
+                    return Task::none();
+                };
+                self.focused_output = Some(output.name.clone());
+                let rect = geometry::compute_rect(output.rect, &self.config);
+                tracing::info!(
+                    "output {} {}x{} scale {} -> rect {rect}",
+                    output.name,
+                    output.rect.width,
+                    output.rect.height,
+                    output.scale
+                );
+            }
+            ToplevelEvent::FocusMoved(name) => {
+                tracing::debug!("Focus moved to output {name}");
+                self.focused_output = Some(name);
+            }
+            ToplevelEvent::Shown(output) => {
+                let Some(output) = output else {
+                    tracing::info!("Terminal shown on an unknown output");
+                    return Task::none();
+                };
+                let rect = geometry::compute_rect(output.rect, &self.config);
+                tracing::info!(
+                    "Terminal shown on output {} {}x{}+{}+{} scale {} -> rect {rect}",
+                    output.name,
+                    output.rect.width,
+                    output.rect.height,
+                    output.rect.x,
+                    output.rect.y,
+                    output.scale
+                );
+            }
+            ToplevelEvent::OutputChanged(output) => {
+                let rect = geometry::compute_rect(output.rect, &self.config);
+                tracing::info!(
+                    "Output {} is {}x{} scale {} -> rect {rect}",
+                    output.name,
+                    output.rect.width,
+                    output.rect.height,
+                    output.scale
+                );
+                self.outputs.retain(|o| o.name != output.name);
+                self.outputs.push(output);
+                self.update_monitors();
+            }
+            ToplevelEvent::OutputRemoved(name) => {
+                tracing::info!("Output {name} removed");
+                self.outputs.retain(|o| o.name != name);
+                self.update_monitors();
+            }
+            ToplevelEvent::Found(app_id) => {
+                if std::mem::take(&mut self.reconnecting) {
+                    tracing::info!("Recovered terminal window {app_id} after reconnecting");
+                }
+                self.window_app_id = Some(app_id);
                 tracing::info!("Terminal window found");
+                self.stats.windows_adopted.fetch_add(1, Ordering::Relaxed);
                 if self.state == ToggleState::WaitingForWindow {
-                    self.state = ToggleState::Visible;
-                    self.focused = true;
+                    let delay = self.config.post_found_delay_ms;
+                    if delay == 0 {
+                        self.window_ready();
+                    } else {
+                        return cosmic::task::future(async move {
+                            tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
+                            Message::WindowReady
+                        });
+                    }
+                }
+            }
+            ToplevelEvent::Toplevels(toplevels) => {
+                self.toplevels = toplevels;
+            }
+            ToplevelEvent::Workspaces(workspaces) => {
+                let same = |a: &WorkspaceInfo, b: &WorkspaceInfo| {
+                    a.name == b.name && a.outputs == b.outputs
+                };
+                for added in workspaces
+                    .iter()
+                    .filter(|w| !self.workspaces.iter().any(|old| same(old, w)))
+                {
+                    tracing::info!(
+                        "Workspace {} \"{}\" on {:?} appeared",
+                        added.number,
+                        added.name,
+                        added.outputs
+                    );
+                }
+                for removed in self
+                    .workspaces
+                    .iter()
+                    .filter(|old| !workspaces.iter().any(|w| same(old, w)))
+                {
+                    tracing::info!(
+                        "Workspace \"{}\" on {:?} removed",
+                        removed.name,
+                        removed.outputs
+                    );
                 }
+                self.workspaces = workspaces;
+            }
+            ToplevelEvent::ScanMissed(app_ids) => {
+                if self.state != ToggleState::WaitingForWindow {
+                    return Task::none();
+                }
+                self.window_scans += 1;
+                tracing::info!(
+                    "Terminal window not found yet (scan {}/{}), expected one of {:?}, \
+                     open windows have app_ids {:?}",
+                    self.window_scans,
+                    self.config.window_scan_retries,
+                    self.terminal_app_ids,
+                    app_ids
+                );
+                let retries = self.config.window_scan_retries;
+                if retries > 0 && self.window_scans >= retries {
+                    let title = self
+                        .title_token
+                        .take()
+                        .filter(|_| self.wayland_controller.is_some());
+                    if let Some(title) = title {
+                        tracing::info!("Looking for a window titled {title} instead");
+                        if let Some(ref controller) = self.wayland_controller {
+                            controller.adopt_titled();
+                        }
+                    } else {
+                        self.spawn_timed_out();
+                    }
+                }
+            }
+            ToplevelEvent::ActivationBlocked => {
+                // The window stays minimized/parked
+                self.state = ToggleState::Hidden;
+                self.focused = false;
             }
             ToplevelEvent::Minimized => {
+                if self.stale_report(false) {
+                    tracing::debug!("Ignoring minimize reported before our show took effect");
+                    return Task::none();
+                }
+                self.reactivate_pending = false;
                 if self.terminal_pid.is_some() {
                     if self.refocusing {
                         // Compositor confirmed minimize — now activate to bring to front
@@ -444,37 +1783,254 @@ impl QuakeTerminal {
                 }
             }
             ToplevelEvent::Activated => {
-                if self.terminal_pid.is_some() {
+                if self.stale_report(true) {
+                    tracing::debug!("Ignoring activation reported before our hide took effect");
+                    return Task::none();
+                }
+                // A window found during `post_found_delay_ms` is set up by
+                // `WindowReady`
+                if self.terminal_pid.is_some() && self.state != ToggleState::WaitingForWindow {
                     self.state = ToggleState::Visible;
                     self.focused = true;
                 }
+                if std::mem::take(&mut self.post_show_pending) {
+                    if let Some(ref command) = self.config.post_show_command {
+                        process::run_hook("post-show", command, &self.hook_env());
+                    }
+                }
+                // Some terminals miss the focus change that comes with being
+                // restored; a second activation once mapped gets it through
+                if std::mem::take(&mut self.reactivate_pending) {
+                    tracing::debug!("Terminal activated, activating again as configured");
+                    if let Some(ref controller) = self.wayland_controller {
+                        controller.activate();
+                    }
+                }
+                return self.set_opacity_dimmed(false);
             }
             ToplevelEvent::Deactivated => {
                 if self.terminal_pid.is_some() {
                     self.focused = false;
+                    return self.set_opacity_dimmed(true);
                 }
             }
             ToplevelEvent::Closed => {
                 tracing::info!("Terminal window closed by compositor");
+                self.window_app_id = None;
+                self.post_show_pending = false;
                 self.state = ToggleState::Idle;
                 self.focused = false;
-                if let Some(pid) = self.terminal_pid.take() {
-                    let raw = pid.load(Ordering::Relaxed) as i32;
-                    let nix_pid = nix::unistd::Pid::from_raw(raw);
-                    let _ = nix::sys::signal::kill(nix_pid, nix::sys::signal::Signal::SIGTERM);
-                    let _ = nix::sys::wait::waitpid(
-                        nix_pid,
-                        Some(nix::sys::wait::WaitPidFlag::WNOHANG),
-                    );
+                self.terminate_terminal(false);
+                if self.pending_respawn {
+                    self.pending_respawn = false;
+                    self.handle_toggle();
+                } else if self.config.quit_daemon_on_close {
+                    tracing::info!("Terminal closed, quitting as configured");
+                    return cosmic::task::message(Message::Quit);
+                }
+            }
+            ToplevelEvent::TrackingReset(hidden) => {
+                self.in_flight = None;
+                self.refocusing = false;
+                self.reactivate_pending = false;
+                match hidden {
+                    // `Found` already set up the window of a fresh spawn
+                    _ if self.state == ToggleState::WaitingForWindow => {}
+                    Some(hidden) => {
+                        tracing::info!(
+                            "Tracking reset: terminal window is {}",
+                            if hidden { "hidden" } else { "shown" }
+                        );
+                        self.state = if hidden {
+                            ToggleState::Hidden
+                        } else {
+                            ToggleState::Visible
+                        };
+                        self.focused = !hidden;
+                    }
+                    None => {
+                        tracing::info!(
+                            "Tracking reset: no terminal window, the next toggle spawns one"
+                        );
+                        self.window_app_id = None;
+                        self.state = ToggleState::Idle;
+                        self.focused = false;
+                    }
+                }
+            }
+            ToplevelEvent::ControllerLost => {
+                self.wayland_controller = None;
+                self.refocusing = false;
+                if self.panic_restarts >= MAX_WAYLAND_RESTARTS {
+                    tracing::error!("Wayland loop lost too often, not restarting it");
+                    return Task::none();
                 }
+                // The new loop re-adopts a still open terminal window and
+                // reports its current state.
+                self.panic_restarts += 1;
+                self.wayland_generation += 1;
+                self.stats
+                    .controller_restarts
+                    .fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    "Restarting Wayland loop ({}/{MAX_WAYLAND_RESTARTS})",
+                    self.panic_restarts
+                );
             }
+            ToplevelEvent::Disconnected => {
+                self.wayland_controller = None;
+                self.refocusing = false;
+                if self.reconnects >= MAX_WAYLAND_RESTARTS {
+                    tracing::error!("Wayland connection lost too often, not reconnecting");
+                    return Task::none();
+                }
+                // The new loop re-adopts a terminal that survived and
+                // reports its current state
+                tracing::warn!(
+                    "Wayland connection lost, reconnecting in {}s",
+                    RECONNECT_DELAY.as_secs()
+                );
+                return cosmic::task::future(async {
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    Message::Reconnect
+                });
+            }
+        }
+        Task::none()
+    }
+}
+
+/// Toggles on SIGUSR1 and hides on SIGUSR2, so scripts can control the
+/// daemon with `kill` where D-Bus isn't available. Other signals keep their
+/// default handling.
+fn signal_subscription() -> Subscription<Message> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    Subscription::run_with_id(
+        "signals",
+        futures::stream::unfold(None, |signals| async move {
+            let (mut usr1, mut usr2) = match signals {
+                Some(signals) => signals,
+                None => match (
+                    signal(SignalKind::user_defined1()),
+                    signal(SignalKind::user_defined2()),
+                ) {
+                    (Ok(usr1), Ok(usr2)) => (usr1, usr2),
+                    (Err(e), _) | (_, Err(e)) => {
+                        tracing::warn!("Failed to listen for SIGUSR1/SIGUSR2: {e}");
+                        return None;
+                    }
+                },
+            };
+            let message = {
+                let toggle = std::pin::pin!(usr1.recv());
+                let hide = std::pin::pin!(usr2.recv());
+                match futures::future::select(toggle, hide).await {
+                    futures::future::Either::Left(_) => Message::Toggle,
+                    futures::future::Either::Right(_) => Message::Hide,
+                }
+            };
+            Some((message, Some((usr1, usr2))))
+        }),
+    )
+}
+
+/// The region typed in the settings window; empty text clears it.
+fn parse_region_input(text: &str) -> Result<Option<Region>, String> {
+    if text.trim().is_empty() {
+        Ok(None)
+    } else {
+        text.parse().map(Some)
+    }
+}
+
+/// Focused, primary and the connected outputs by name. A configured output
+/// that is disconnected stays listed so the selection doesn't change.
+fn monitor_choices(outputs: &[&str], current: &Monitor) -> Vec<Monitor> {
+    let mut names = outputs.to_vec();
+    names.sort_unstable();
+    let mut choices = vec![Monitor::Focused, Monitor::Primary];
+    choices.extend(
+        names
+            .into_iter()
+            .map(|name| Monitor::Named(name.to_string())),
+    );
+    if !choices.contains(current) {
+        choices.push(current.clone());
+    }
+    choices
+}
+
+/// The output the terminal is sized for with `size_flags`, or None while
+/// it isn't known, e.g. before any window was focused.
+fn size_output<'a>(
+    monitor: &Monitor,
+    outputs: &'a [OutputInfo],
+    focused: Option<&str>,
+) -> Option<&'a OutputInfo> {
+    let name = match monitor {
+        Monitor::Named(name) => name.as_str(),
+        Monitor::Focused => focused?,
+        // The Wayland loop shows the terminal on the first output, too
+        Monitor::Primary => return outputs.first(),
+    };
+    outputs.iter().find(|output| output.name == name)
+}
+
+/// A settings row with `label` beside `input`, or above it in the compact
+/// layout.
+fn labeled_item<'a>(
+    compact: bool,
+    label: String,
+    input: impl Into<Element<'a, Message>>,
+) -> Element<'a, Message> {
+    if compact {
+        settings::item_row(vec![widget::column()
+            .push(text(label))
+            .push(input)
+            .spacing(4)
+            .into()])
+        .into()
+    } else {
+        settings::item(label, input).into()
+    }
+}
+
+/// Reads or writes a config value directly. A running daemon picks up the
+/// change through its config subscription, so this needs no IPC.
+fn config_command(op: ConfigOp) -> cosmic::iced::Result {
+    let handler = match cosmic_config::Config::new(APP_ID, CONFIG_VERSION) {
+        Ok(handler) => handler,
+        Err(e) => {
+            eprintln!("Failed to open config: {e}");
+            std::process::exit(1);
         }
+    };
+    let mut config = QuakeConfig::get_entry(&handler).unwrap_or_else(|(_, config)| config);
+    config.validate_and_fix();
+
+    let result = match op {
+        ConfigOp::Get { key } => config.get_key(&key).map(|value| println!("{value}")),
+        ConfigOp::Set { key, value } => config
+            .set_key(&handler, &key, &value)
+            .map(|()| println!("{key} = {value}")),
+        // The running daemon's config may include overrides and corrections
+        ConfigOp::Dump => stats::fetch_config().map(|json| println!("{json}")),
+    };
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
     }
+    Ok(())
 }
 
 pub fn run() -> cosmic::iced::Result {
     let args = Args::parse();
 
+    if let Some(CliCommand::Config { op }) = args.subcommand {
+        return config_command(op);
+    }
+
     cosmic::app::run_single_instance::<QuakeTerminal>(
         Settings::default()
             .no_main_window(true)
@@ -482,3 +2038,234 @@ pub fn run() -> cosmic::iced::Result {
         args,
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found() -> ToplevelEvent {
+        ToplevelEvent::Found(String::from(process::QUAKE_APP_ID))
+    }
+
+    /// A daemon that just spawned a terminal with `spawn_behavior`.
+    fn spawned_app(spawn_behavior: SpawnBehavior) -> QuakeTerminal {
+        let config = QuakeConfig {
+            spawn_behavior,
+            post_found_delay_ms: 0,
+            ..QuakeConfig::default()
+        };
+        let mut app = QuakeTerminal::new(Core::default(), config, None);
+        app.spawned(process::SpawnResult {
+            pid: 0,
+            start_time: None,
+            app_ids: vec![String::from(process::QUAKE_APP_ID)],
+        });
+        app
+    }
+
+    #[test]
+    fn toggle_after_settings_only_startup_spawns() {
+        // A command that can't be started: the spawn is attempted, but no
+        // process is launched
+        let config = QuakeConfig {
+            terminal_command: String::from("/nonexistent/quake-test-terminal"),
+            detach_strategy: DetachStrategy::None,
+            ..QuakeConfig::default()
+        };
+        let mut app = QuakeTerminal::new(Core::default(), config, None);
+        let _ = app.update(Message::OpenSettings);
+        let id = app.settings_window_id.expect("settings window opened");
+        let _ = app.update(Message::WindowOpened(id));
+        let _ = app.update(Message::WindowFocused(id, true));
+        let _ = app.update(Message::CloseWindow(id));
+        assert_eq!(app.state, ToggleState::Idle);
+
+        let _ = app.update(Message::Toggle);
+        assert_eq!(app.stats.toggles.load(Ordering::Relaxed), 1);
+        assert_eq!(app.stats.spawn_failures.load(Ordering::Relaxed), 1);
+        assert!(app.terminal_pid.is_none());
+    }
+
+    #[test]
+    fn show_spawn_shows_the_window_when_it_appears() {
+        let mut app = spawned_app(SpawnBehavior::Show);
+        let _ = app.handle_toplevel_event(found());
+        assert_eq!(app.state, ToggleState::Visible);
+    }
+
+    #[test]
+    fn hidden_spawn_keeps_the_window_hidden_until_the_next_toggle() {
+        let mut app = spawned_app(SpawnBehavior::Hidden);
+        let _ = app.handle_toplevel_event(found());
+        assert_eq!(app.state, ToggleState::Hidden);
+
+        app.handle_toggle();
+        assert_eq!(app.state, ToggleState::Visible);
+    }
+
+    #[test]
+    fn hidden_spawn_shows_after_a_toggle_while_starting() {
+        let mut app = spawned_app(SpawnBehavior::Hidden);
+        app.handle_toggle();
+        assert_eq!(app.state, ToggleState::WaitingForWindow);
+
+        let _ = app.handle_toplevel_event(found());
+        assert_eq!(app.state, ToggleState::Visible);
+    }
+
+    #[test]
+    fn repeated_toggles_while_starting_still_show() {
+        let mut app = spawned_app(SpawnBehavior::Show);
+        app.handle_toggle();
+        app.handle_toggle();
+
+        let _ = app.handle_toplevel_event(found());
+        assert_eq!(app.state, ToggleState::Visible);
+    }
+
+    /// A daemon showing its terminal, as after the window was found.
+    fn visible_app() -> QuakeTerminal {
+        let mut app = spawned_app(SpawnBehavior::Show);
+        let _ = app.handle_toplevel_event(found());
+        app
+    }
+
+    #[test]
+    fn late_activation_does_not_undo_a_hide() {
+        let mut app = visible_app();
+        app.handle_toggle();
+        assert_eq!(app.state, ToggleState::Hidden);
+
+        // Reported for the show before the hide
+        let _ = app.handle_toplevel_event(ToplevelEvent::Activated);
+        assert_eq!(app.state, ToggleState::Hidden);
+
+        let _ = app.handle_toplevel_event(ToplevelEvent::Minimized);
+        assert_eq!(app.state, ToggleState::Hidden);
+    }
+
+    #[test]
+    fn manual_restore_after_a_confirmed_hide_is_trusted() {
+        let mut app = visible_app();
+        app.handle_toggle();
+        let _ = app.handle_toplevel_event(ToplevelEvent::Minimized);
+
+        let _ = app.handle_toplevel_event(ToplevelEvent::Activated);
+        assert_eq!(app.state, ToggleState::Visible);
+    }
+
+    #[test]
+    fn confirmations_record_latency() {
+        let mut app = visible_app();
+        app.handle_toggle();
+        app.in_flight = Some((false, Instant::now() - std::time::Duration::from_millis(40)));
+        let _ = app.handle_toplevel_event(ToplevelEvent::Minimized);
+        assert!(app.stats.hide_latency_ms.load(Ordering::Relaxed) >= 40);
+
+        // Reports without a command in flight measure nothing
+        let _ = app.handle_toplevel_event(ToplevelEvent::Activated);
+        assert_eq!(app.stats.show_latency_ms.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn tracking_reset_takes_the_state_of_the_window_found() {
+        let mut app = visible_app();
+        let _ = app.handle_toplevel_event(ToplevelEvent::TrackingReset(Some(true)));
+        assert_eq!(app.state, ToggleState::Hidden);
+
+        let _ = app.handle_toplevel_event(ToplevelEvent::TrackingReset(None));
+        assert_eq!(app.state, ToggleState::Idle);
+    }
+
+    #[test]
+    fn panic_restarts_are_capped_until_the_loop_connects() {
+        let mut app = QuakeTerminal::new(Core::default(), QuakeConfig::default(), None);
+        for _ in 0..MAX_WAYLAND_RESTARTS + 1 {
+            let _ = app.handle_toplevel_event(ToplevelEvent::ControllerLost);
+        }
+        assert_eq!(app.wayland_generation, MAX_WAYLAND_RESTARTS);
+
+        let _ = app.handle_toplevel_event(ToplevelEvent::Connected);
+        let _ = app.handle_toplevel_event(ToplevelEvent::ControllerLost);
+        assert_eq!(app.wayland_generation, MAX_WAYLAND_RESTARTS + 1);
+    }
+
+    #[test]
+    fn manual_minimize_after_the_settle_time_is_trusted() {
+        let mut app = visible_app();
+        app.handle_toggle();
+        app.handle_toggle();
+        assert_eq!(app.state, ToggleState::Visible);
+        app.in_flight = Some((true, Instant::now() - COMMAND_SETTLE * 2));
+
+        let _ = app.handle_toplevel_event(ToplevelEvent::Minimized);
+        assert_eq!(app.state, ToggleState::Hidden);
+    }
+
+    #[test]
+    fn size_flags_follow_the_selected_output() {
+        let output = |name: &str| OutputInfo {
+            name: String::from(name),
+            rect: geometry::Rect {
+                x: 0,
+                y: 0,
+                width: 1920,
+                height: 1080,
+            },
+            scale: 1,
+        };
+        let outputs = [output("DP-1"), output("HDMI-A-1")];
+        let name = |monitor: &Monitor, focused: Option<&str>| {
+            size_output(monitor, &outputs, focused).map(|o| o.name.as_str())
+        };
+
+        assert_eq!(name(&Monitor::Focused, Some("HDMI-A-1")), Some("HDMI-A-1"));
+        assert_eq!(name(&Monitor::Focused, None), None);
+        assert_eq!(name(&Monitor::Primary, Some("HDMI-A-1")), Some("DP-1"));
+        let named = Monitor::Named(String::from("HDMI-A-1"));
+        assert_eq!(name(&named, Some("DP-1")), Some("HDMI-A-1"));
+        let unplugged = Monitor::Named(String::from("DP-2"));
+        assert_eq!(name(&unplugged, Some("DP-1")), None);
+    }
+
+    #[test]
+    fn monitor_choices_list_outputs_by_name() {
+        assert_eq!(
+            monitor_choices(&["HDMI-A-1", "DP-1"], &Monitor::Focused),
+            vec![
+                Monitor::Focused,
+                Monitor::Primary,
+                Monitor::Named(String::from("DP-1")),
+                Monitor::Named(String::from("HDMI-A-1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn monitor_choices_keep_a_disconnected_selection() {
+        let unplugged = Monitor::Named(String::from("DP-2"));
+        assert_eq!(
+            monitor_choices(&["DP-1"], &unplugged).last(),
+            Some(&unplugged)
+        );
+    }
+
+    #[test]
+    fn actions_round_trip_through_their_dbus_names() {
+        // Actions reach a running daemon as their Display string
+        for action in [
+            QuakeAction::Toggle { arg_profile: None },
+            QuakeAction::Toggle {
+                arg_profile: Some(String::from("work")),
+            },
+            QuakeAction::Show,
+            QuakeAction::Hide,
+            QuakeAction::ToggleHere,
+            QuakeAction::Quit,
+            QuakeAction::ResetTracking,
+        ] {
+            let name = action.to_string();
+            assert_eq!(name.parse::<QuakeAction>().map(|a| a.to_string()), Ok(name));
+        }
+    }
+}
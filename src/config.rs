@@ -1,12 +1,24 @@
-use cosmic_config::CosmicConfigEntry;
+use std::collections::HashMap;
+
+use cosmic_config::{ConfigSet, CosmicConfigEntry};
 use serde::{Deserialize, Serialize};
 
 pub const CONFIG_VERSION: u64 = 1;
 
+/// Overrides `terminal_command` without saving it.
+const TERMINAL_ENV: &str = "COSMIC_QUAKE_TERMINAL";
+/// Overrides `terminal_args`, split on whitespace, without saving them.
+const TERMINAL_ARGS_ENV: &str = "COSMIC_QUAKE_TERMINAL_ARGS";
+
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub enum Monitor {
+    /// Wherever the compositor puts the terminal
     #[default]
     Focused,
+    /// The first output the compositor advertises
+    Primary,
+    /// The output with this connector name, e.g. `DP-1`
+    Named(String),
 }
 
 #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
@@ -25,6 +37,89 @@ impl std::fmt::Display for Position {
     }
 }
 
+/// A part of every output, in percent of its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Written as `x y width height`, as in the settings window.
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {} {}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl std::str::FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split_whitespace()
+            .map(|v| {
+                v.parse::<u32>()
+                    .map_err(|_| format!("{v} is not a percentage"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match values[..] {
+            [x, y, width, height] => Ok(Region {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(String::from("expected x, y, width and height")),
+        }
+    }
+}
+
+/// How the terminal is hidden on toggle.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum HideStrategy {
+    #[default]
+    Minimize,
+    /// Park the window on the given (1-based) workspace of its output
+    MoveToWorkspace(u32),
+}
+
+/// What the first toggle does when no terminal is running.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum SpawnBehavior {
+    /// Spawn the terminal and show it as soon as its window appears
+    #[default]
+    Show,
+    /// Spawn the terminal hidden; the next toggle shows it
+    Hidden,
+}
+
+/// What a toggle does while the terminal is visible but another window
+/// has focus.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum UnfocusedToggle {
+    /// Bring the terminal back to the front and focus it
+    #[default]
+    Raise,
+    /// Hide it, as if it were focused
+    Hide,
+}
+
+/// How the spawned terminal is separated from the daemon, so it can
+/// outlive a daemon restart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum DetachStrategy {
+    /// Spawn as a plain child process
+    #[default]
+    None,
+    /// Start the terminal in its own session
+    Setsid,
+    /// Run it in a transient systemd user scope, outside the daemon's
+    /// service cgroup
+    SystemdScope,
+}
+
 #[derive(
     Debug,
     Clone,
@@ -37,10 +132,79 @@ impl std::fmt::Display for Position {
 pub struct QuakeConfig {
     pub terminal_command: String,
     pub terminal_args: Vec<String>,
+    /// The terminal selected before `terminal_command`, for `SwapTerminal`
+    pub previous_terminal_command: String,
+    /// Extra `(command, display_name, icon_name)` entries shown in the picker
+    pub custom_terminals: Vec<(String, String, String)>,
+    /// Theme file or name passed to the terminal's theme flag
+    pub theme: Option<String>,
     pub height_percent: u32,
     pub width_percent: u32,
     pub monitor: Monitor,
     pub position: Position,
+    pub hide_strategy: HideStrategy,
+    /// Show the terminal even while a fullscreen window is focused
+    pub raise_over_fullscreen: bool,
+    pub spawn_behavior: SpawnBehavior,
+    pub toggle_when_unfocused: UnfocusedToggle,
+    /// Toggle when the pointer is pushed against `toggle_edge`
+    pub edge_toggle: bool,
+    pub toggle_edge: Position,
+    /// Part of each output the dropdown keeps clear of, e.g. a pinned
+    /// sidebar
+    pub exclude_region: Option<Region>,
+    /// Shell command run right before the terminal is hidden
+    pub pre_hide_command: Option<String>,
+    /// Shell command run right after the terminal is shown
+    pub post_show_command: Option<String>,
+    /// Shell command that closes the terminal cleanly, run instead of
+    /// sending SIGTERM right away
+    pub close_command: Option<String>,
+    /// Close the settings window when the terminal is toggled
+    pub close_settings_on_toggle: bool,
+    /// App_ids that are never adopted as the terminal window
+    pub ignore_app_ids: Vec<String>,
+    /// Rescans for a missing terminal window before giving up, 0 for never
+    pub window_scan_retries: u32,
+    pub detach_strategy: DetachStrategy,
+    /// Exit the daemon when the user closes the terminal window
+    pub quit_daemon_on_close: bool,
+    /// Activate the terminal a second time once it reports being focused,
+    /// for terminals that draw as unfocused after being restored
+    pub reactivate_on_show: bool,
+    /// Spawn the terminal when the daemon starts instead of on the first
+    /// toggle
+    pub prewarm: bool,
+    /// Leave the prewarmed terminal shown instead of hiding it
+    pub show_on_startup: bool,
+    /// Shell commands run in order in each freshly spawned terminal
+    pub startup_commands: Vec<String>,
+    /// Pass the dropdown size to terminals that take a size in pixels
+    pub size_flags: bool,
+    /// Title of the terminal window, for terminals with a title flag
+    pub window_title: Option<String>,
+    /// Shell the terminal runs instead of `$SHELL`
+    pub shell: Option<String>,
+    /// Start the terminal with the environment of the user's login shell
+    pub inherit_login_env: bool,
+    /// tmux session the terminal attaches to, so its content survives
+    /// respawns
+    pub tmux_session: Option<String>,
+    /// Wait after a spawned terminal's window appears before hiding or
+    /// moving it, for terminals that map their window late
+    pub post_found_delay_ms: u64,
+    /// Command the terminal is started through, e.g. `firejail`
+    pub launch_prefix: Vec<String>,
+    /// Named argument sets added to `terminal_args` by `toggle --arg-profile`
+    pub arg_profiles: HashMap<String, Vec<String>>,
+    /// Background opacity of the terminal while it is unfocused, for
+    /// terminals with runtime opacity control
+    pub unfocused_opacity_percent: Option<u32>,
+    /// Open directories passed over D-Bus in a new tab of a running kitty
+    /// instead of just showing it
+    pub open_in_tab: bool,
+    /// Ask the compositor to keep the settings window above the terminal
+    pub settings_always_on_top: bool,
 }
 
 impl Default for QuakeConfig {
@@ -48,10 +212,297 @@ impl Default for QuakeConfig {
         Self {
             terminal_command: String::from("cosmic-term"),
             terminal_args: Vec::new(),
+            previous_terminal_command: String::new(),
+            custom_terminals: Vec::new(),
+            theme: None,
             height_percent: 40,
             width_percent: 100,
             monitor: Monitor::default(),
             position: Position::default(),
+            hide_strategy: HideStrategy::default(),
+            raise_over_fullscreen: true,
+            spawn_behavior: SpawnBehavior::default(),
+            toggle_when_unfocused: UnfocusedToggle::default(),
+            edge_toggle: false,
+            toggle_edge: Position::default(),
+            exclude_region: None,
+            pre_hide_command: None,
+            post_show_command: None,
+            close_command: None,
+            close_settings_on_toggle: false,
+            ignore_app_ids: Vec::new(),
+            window_scan_retries: 5,
+            detach_strategy: DetachStrategy::default(),
+            quit_daemon_on_close: false,
+            reactivate_on_show: false,
+            prewarm: false,
+            show_on_startup: false,
+            startup_commands: Vec::new(),
+            size_flags: false,
+            window_title: None,
+            shell: None,
+            inherit_login_env: false,
+            tmux_session: None,
+            post_found_delay_ms: 100,
+            launch_prefix: Vec::new(),
+            arg_profiles: HashMap::new(),
+            unfocused_opacity_percent: None,
+            open_in_tab: false,
+            settings_always_on_top: true,
         }
     }
 }
+
+impl QuakeConfig {
+    /// Rejects values that would make the dropdown unusable.
+    pub fn check(&self) -> Result<(), String> {
+        if self.terminal_command.trim().is_empty() {
+            return Err(String::from("terminal_command must not be empty"));
+        }
+        if !(1..=100).contains(&self.height_percent) {
+            return Err(String::from("height_percent must be between 1 and 100"));
+        }
+        if !(1..=100).contains(&self.width_percent) {
+            return Err(String::from("width_percent must be between 1 and 100"));
+        }
+        Ok(())
+    }
+
+    /// Corrects values a hand-edited config may have out of range, logging
+    /// each correction, so a bad config can't produce an unusable window.
+    pub fn validate_and_fix(&mut self) {
+        let defaults = Self::default();
+        if self.terminal_command.trim().is_empty() {
+            tracing::warn!(
+                "terminal_command is empty, using {}",
+                defaults.terminal_command
+            );
+            self.terminal_command = defaults.terminal_command;
+        } else if self.terminal_command.trim() != self.terminal_command {
+            self.terminal_command = self.terminal_command.trim().to_string();
+        }
+        for (name, percent) in [
+            ("height_percent", &mut self.height_percent),
+            ("width_percent", &mut self.width_percent),
+        ] {
+            let clamped = (*percent).clamp(1, 100);
+            if clamped != *percent {
+                tracing::warn!("{name} {percent} is out of range, using {clamped}");
+                *percent = clamped;
+            }
+        }
+        for (name, list) in [
+            ("terminal_args", &mut self.terminal_args),
+            ("startup_commands", &mut self.startup_commands),
+            ("launch_prefix", &mut self.launch_prefix),
+        ] {
+            let len = list.len();
+            list.retain(|entry| !entry.trim().is_empty());
+            if list.len() != len {
+                tracing::warn!("Dropped empty entries from {name}");
+            }
+        }
+        if self
+            .theme
+            .as_deref()
+            .is_some_and(|theme| theme.trim().is_empty())
+        {
+            tracing::warn!("theme is empty, using none");
+            self.theme = None;
+        }
+        // The terminal is appended to the prefix; naming it there too would
+        // make the terminal run a copy of itself as its program
+        if self.launch_prefix.last() == Some(&self.terminal_command) {
+            tracing::warn!("launch_prefix ends with the terminal command, dropping it");
+            self.launch_prefix.pop();
+        }
+        if let Some(percent) = self.unfocused_opacity_percent.filter(|p| *p > 100) {
+            tracing::warn!("unfocused_opacity_percent {percent} is out of range, using 100");
+            self.unfocused_opacity_percent = Some(100);
+        }
+        for value in [
+            &mut self.window_title,
+            &mut self.shell,
+            &mut self.tmux_session,
+        ] {
+            if value.as_deref().is_some_and(|v| v.trim().is_empty()) {
+                *value = None;
+            }
+        }
+    }
+
+    /// Applies the terminal overrides from the environment. They are never
+    /// saved, so they have to be applied again to every config update.
+    pub fn apply_env_overrides(&mut self) {
+        self.apply_overrides(
+            std::env::var(TERMINAL_ENV).ok(),
+            std::env::var(TERMINAL_ARGS_ENV).ok(),
+        );
+    }
+
+    fn apply_overrides(&mut self, command: Option<String>, args: Option<String>) {
+        if let Some(command) = command.filter(|c| !c.trim().is_empty()) {
+            tracing::info!("{TERMINAL_ENV} is set, using {command} as the terminal");
+            self.terminal_command = command.trim().to_string();
+        }
+        if let Some(args) = args {
+            tracing::info!("{TERMINAL_ARGS_ENV} is set, using '{args}' as terminal arguments");
+            self.terminal_args = args.split_whitespace().map(String::from).collect();
+        }
+    }
+}
+
+/// Implements by-name access to the listed fields for `config get/set`.
+/// Values are written in RON, the same format as the config files.
+macro_rules! config_keys {
+    ($($field:ident),* $(,)?) => {
+        impl QuakeConfig {
+            /// Returns the RON representation of `key`.
+            pub fn get_key(&self, key: &str) -> Result<String, String> {
+                match key {
+                    $(stringify!($field) => ron::to_string(&self.$field).map_err(|e| e.to_string()),)*
+                    _ => Err(format!("Unknown config key: {key}")),
+                }
+            }
+
+            /// Parses `value` as RON, checks the result and persists it.
+            pub fn set_key(
+                &mut self,
+                config: &cosmic_config::Config,
+                key: &str,
+                value: &str,
+            ) -> Result<(), String> {
+                match key {
+                    $(stringify!($field) => {
+                        let mut updated = self.clone();
+                        updated.$field = ron::from_str(value)
+                            .map_err(|e| format!("Invalid value for {key}: {e}"))?;
+                        updated.check()?;
+                        config.set(key, &updated.$field).map_err(|e| e.to_string())?;
+                        *self = updated;
+                        Ok(())
+                    })*
+                    _ => Err(format!("Unknown config key: {key}")),
+                }
+            }
+        }
+    };
+}
+
+config_keys!(
+    terminal_command,
+    terminal_args,
+    previous_terminal_command,
+    custom_terminals,
+    theme,
+    height_percent,
+    width_percent,
+    monitor,
+    position,
+    hide_strategy,
+    raise_over_fullscreen,
+    spawn_behavior,
+    toggle_when_unfocused,
+    edge_toggle,
+    toggle_edge,
+    exclude_region,
+    pre_hide_command,
+    post_show_command,
+    close_command,
+    close_settings_on_toggle,
+    ignore_app_ids,
+    window_scan_retries,
+    detach_strategy,
+    quit_daemon_on_close,
+    reactivate_on_show,
+    prewarm,
+    show_on_startup,
+    startup_commands,
+    size_flags,
+    window_title,
+    shell,
+    inherit_login_env,
+    tmux_session,
+    post_found_delay_ms,
+    launch_prefix,
+    arg_profiles,
+    unfocused_opacity_percent,
+    open_in_tab,
+    settings_always_on_top,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_and_fix_corrects_out_of_range_values() {
+        let mut config = QuakeConfig {
+            terminal_command: String::from("  "),
+            terminal_args: vec![String::from("-e"), String::new(), String::from(" ")],
+            theme: Some(String::new()),
+            height_percent: 500,
+            width_percent: 0,
+            unfocused_opacity_percent: Some(150),
+            ..QuakeConfig::default()
+        };
+        config.validate_and_fix();
+
+        assert_eq!(
+            config.terminal_command,
+            QuakeConfig::default().terminal_command
+        );
+        assert_eq!(config.terminal_args, vec![String::from("-e")]);
+        assert_eq!(config.theme, None);
+        assert_eq!(config.height_percent, 100);
+        assert_eq!(config.width_percent, 1);
+        assert_eq!(config.unfocused_opacity_percent, Some(100));
+        assert_eq!(config.check(), Ok(()));
+    }
+
+    #[test]
+    fn launch_prefix_does_not_repeat_the_terminal() {
+        let mut config = QuakeConfig {
+            terminal_command: String::from("kitty"),
+            launch_prefix: vec![String::from("firejail"), String::from("kitty")],
+            ..QuakeConfig::default()
+        };
+        config.validate_and_fix();
+        assert_eq!(config.launch_prefix, vec![String::from("firejail")]);
+    }
+
+    #[test]
+    fn overrides_replace_command_and_args() {
+        let mut config = QuakeConfig {
+            terminal_args: vec![String::from("--old")],
+            ..QuakeConfig::default()
+        };
+        config.apply_overrides(Some(String::from(" foot ")), Some(String::from("-e  htop")));
+
+        assert_eq!(config.terminal_command, "foot");
+        assert_eq!(config.terminal_args, ["-e", "htop"]);
+    }
+
+    #[test]
+    fn empty_command_override_is_ignored() {
+        let mut config = QuakeConfig::default();
+        config.apply_overrides(Some(String::new()), None);
+
+        assert_eq!(config, QuakeConfig::default());
+    }
+
+    #[test]
+    fn validate_and_fix_only_trims_valid_config() {
+        let mut config = QuakeConfig {
+            terminal_command: String::from(" alacritty "),
+            ..QuakeConfig::default()
+        };
+        config.validate_and_fix();
+
+        let expected = QuakeConfig {
+            terminal_command: String::from("alacritty"),
+            ..QuakeConfig::default()
+        };
+        assert_eq!(config, expected);
+    }
+}
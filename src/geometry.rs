@@ -0,0 +1,214 @@
+use crate::config::{Position, QuakeConfig, Region};
+
+/// A rectangle in logical (scaled) compositor coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl std::fmt::Display for Rect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "x={} y={} w={} h={}",
+            self.x, self.y, self.width, self.height
+        )
+    }
+}
+
+/// Computes the dropdown rectangle on an output from the size and position
+/// settings. The dropdown is centered horizontally and docked to the
+/// configured edge, then cut back to keep clear of `exclude_region`.
+pub fn compute_rect(output: Rect, config: &QuakeConfig) -> Rect {
+    let rect = dock_rect(output, config);
+    match config.exclude_region {
+        Some(region) => avoid(rect, region_rect(output, region)),
+        None => rect,
+    }
+}
+
+fn dock_rect(output: Rect, config: &QuakeConfig) -> Rect {
+    let width = output.width * config.width_percent.min(100) as i32 / 100;
+    let height = output.height * config.height_percent.min(100) as i32 / 100;
+    let x = output.x + (output.width - width) / 2;
+    let y = match config.position {
+        Position::Top => output.y,
+        Position::Bottom => output.y + output.height - height,
+    };
+
+    Rect {
+        x,
+        y,
+        width,
+        height,
+    }
+}
+
+/// `region` of `output` in compositor coordinates, clipped to the output.
+fn region_rect(output: Rect, region: Region) -> Rect {
+    let x = region.x.min(100);
+    let y = region.y.min(100);
+    Rect {
+        x: output.x + output.width * x as i32 / 100,
+        y: output.y + output.height * y as i32 / 100,
+        width: output.width * region.width.min(100 - x) as i32 / 100,
+        height: output.height * region.height.min(100 - y) as i32 / 100,
+    }
+}
+
+/// The largest part of `rect` left, right, above or below `excluded`.
+/// Returned unchanged if they don't overlap, or if `excluded` covers it.
+fn avoid(rect: Rect, excluded: Rect) -> Rect {
+    let right = rect.x + rect.width;
+    let bottom = rect.y + rect.height;
+    let excluded_right = excluded.x + excluded.width;
+    let excluded_bottom = excluded.y + excluded.height;
+    if excluded.x >= right
+        || excluded_right <= rect.x
+        || excluded.y >= bottom
+        || excluded_bottom <= rect.y
+    {
+        return rect;
+    }
+
+    let candidates = [
+        Rect {
+            width: excluded.x - rect.x,
+            ..rect
+        },
+        Rect {
+            x: excluded_right,
+            width: right - excluded_right,
+            ..rect
+        },
+        Rect {
+            height: excluded.y - rect.y,
+            ..rect
+        },
+        Rect {
+            y: excluded_bottom,
+            height: bottom - excluded_bottom,
+            ..rect
+        },
+    ];
+    candidates
+        .into_iter()
+        .filter(|c| c.width > 0 && c.height > 0)
+        .max_by_key(|c| i64::from(c.width) * i64::from(c.height))
+        .unwrap_or(rect)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: Rect = Rect {
+        x: 0,
+        y: 0,
+        width: 2000,
+        height: 1000,
+    };
+
+    fn config(exclude_region: Option<Region>) -> QuakeConfig {
+        QuakeConfig {
+            width_percent: 100,
+            height_percent: 40,
+            exclude_region,
+            ..QuakeConfig::default()
+        }
+    }
+
+    fn region(x: u32, y: u32, width: u32, height: u32) -> Option<Region> {
+        Some(Region {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    #[test]
+    fn right_sidebar_narrows_the_dropdown() {
+        assert_eq!(
+            compute_rect(OUTPUT, &config(region(80, 0, 20, 100))),
+            Rect {
+                x: 0,
+                y: 0,
+                width: 1600,
+                height: 400
+            }
+        );
+    }
+
+    #[test]
+    fn left_sidebar_moves_the_dropdown_right() {
+        assert_eq!(
+            compute_rect(OUTPUT, &config(region(0, 0, 25, 100))),
+            Rect {
+                x: 500,
+                y: 0,
+                width: 1500,
+                height: 400
+            }
+        );
+    }
+
+    #[test]
+    fn region_below_the_dropdown_changes_nothing() {
+        assert_eq!(
+            compute_rect(OUTPUT, &config(region(0, 50, 100, 50))),
+            compute_rect(OUTPUT, &config(None))
+        );
+    }
+
+    #[test]
+    fn region_across_the_bottom_shortens_the_dropdown() {
+        assert_eq!(
+            compute_rect(OUTPUT, &config(region(0, 30, 100, 70))),
+            Rect {
+                x: 0,
+                y: 0,
+                width: 2000,
+                height: 300
+            }
+        );
+    }
+
+    #[test]
+    fn covering_region_is_ignored() {
+        assert_eq!(
+            compute_rect(OUTPUT, &config(region(0, 0, 100, 100))),
+            compute_rect(OUTPUT, &config(None))
+        );
+    }
+
+    #[test]
+    fn region_is_clipped_to_the_output() {
+        let output = Rect {
+            x: 2000,
+            y: 0,
+            width: 1000,
+            height: 1000,
+        };
+        assert_eq!(
+            region_rect(
+                output,
+                Region {
+                    x: 90,
+                    y: 0,
+                    width: 50,
+                    height: 200
+                }
+            ),
+            Rect {
+                x: 2900,
+                y: 0,
+                width: 100,
+                height: 1000
+            }
+        );
+    }
+}
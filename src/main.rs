@@ -1,7 +1,9 @@
 mod app;
 mod config;
+mod geometry;
 mod i18n;
 mod process;
+mod stats;
 mod wayland;
 
 use cosmic::Application;
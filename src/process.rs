@@ -1,23 +1,179 @@
-use std::process::Command;
-use tracing::{error, info};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+use tracing::{error, info, warn};
+
+use crate::config::DetachStrategy;
 
 pub const QUAKE_APP_ID: &str = "cosmic-ext-quake-terminal";
 
+/// How a terminal must be started so each spawn gets a window of its own
+/// process, which the window tracking relies on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InstanceMode {
+    /// Every invocation starts a new process
+    Standalone,
+    /// GTK single-instance mode must be turned off, or the window is opened
+    /// by an already running instance
+    NoGtkSingleInstance,
+    /// `start --always-new-process`, or a running wezterm opens the window
+    AlwaysNewProcess,
+}
+
+/// (command, display_name, icon_name, instance mode)
+pub const KNOWN_TERMINALS: &[(&str, &str, &str, InstanceMode)] = &[
+    (
+        "cosmic-term",
+        "cosmic-terminal",
+        "com.system76.CosmicTerm",
+        InstanceMode::Standalone,
+    ),
+    (
+        "alacritty",
+        "alacritty",
+        "Alacritty",
+        InstanceMode::Standalone,
+    ),
+    ("kitty", "kitty", "kitty", InstanceMode::Standalone),
+    ("foot", "foot", "foot", InstanceMode::Standalone),
+    (
+        "wezterm",
+        "wezterm",
+        "org.wezfurlong.wezterm",
+        InstanceMode::AlwaysNewProcess,
+    ),
+    (
+        "ghostty",
+        "ghostty",
+        "com.mitchellh.ghostty",
+        InstanceMode::NoGtkSingleInstance,
+    ),
+];
+
+/// Desktop entry IDs of supported terminals and their commands, used to
+/// resolve the user's xdg-terminal-exec preference.
+const TERMINAL_DESKTOP_IDS: &[(&str, &str)] = &[
+    ("com.system76.CosmicTerm", "cosmic-term"),
+    ("Alacritty", "alacritty"),
+    ("kitty", "kitty"),
+    ("foot", "foot"),
+    ("org.wezfurlong.wezterm", "wezterm"),
+    ("com.mitchellh.ghostty", "ghostty"),
+];
+
+/// App_ids some terminal builds use instead of the one `get_class_args`
+/// expects. A new toplevel matching any of them is adopted as well.
+const ALTERNATE_APP_IDS: &[(&str, &[&str])] = &[
+    // Debug builds of ghostty append a suffix to the app_id
+    ("ghostty", &["com.mitchellh.ghostty-debug"]),
+];
+
 pub struct SpawnResult {
     pub pid: u32,
+    /// Start time of the spawned process, to detect PID reuse
+    pub start_time: Option<u64>,
+    pub app_ids: Vec<String>,
+}
+
+/// Per-spawn settings on top of the terminal command.
+#[derive(Debug, Default)]
+pub struct SpawnOptions<'a> {
+    pub args: &'a [String],
+    /// Command the terminal is run through, e.g. `firejail` or `nice -n 10`
+    pub launch_prefix: &'a [String],
+    pub cwd: Option<&'a Path>,
+    pub theme: Option<&'a str>,
+    pub detach: DetachStrategy,
+    /// Shell commands run in order inside the terminal before its shell
+    pub startup_commands: &'a [String],
+    /// Shell to run instead of `$SHELL`
+    pub shell: Option<&'a str>,
+    /// tmux session to attach to, created if it doesn't exist
+    pub tmux_session: Option<&'a str>,
+    /// Add the environment of the user's login shell, see [`login_env`]
+    pub login_env: bool,
+    /// Initial window size in logical pixels, for terminals that take one
+    pub size: Option<(i32, i32)>,
+    pub title: Option<&'a str>,
+    /// Let the terminal be controlled through its remote-control socket,
+    /// see [`remote_control_args`]
+    pub remote_control: bool,
+}
+
+/// A terminal resolved from an installed `.desktop` file.
+#[derive(Debug, PartialEq)]
+pub struct DesktopTerminal {
+    pub command: String,
+    /// Arguments from the Exec line, field codes removed
+    pub args: Vec<String>,
     pub app_id: String,
 }
 
-pub fn spawn_terminal(command: &str, args: &[String]) -> Option<SpawnResult> {
-    let (class_args, app_id) = get_class_args(command);
+pub fn spawn_terminal(command: &str, options: &SpawnOptions) -> Option<SpawnResult> {
+    let desktop = resolve_command(command);
+    let (command, desktop_args) = match desktop {
+        Some(ref entry) => (entry.command.as_str(), entry.args.as_slice()),
+        None => (command, &[][..]),
+    };
+    let (mut class_args, _) = get_class_args(command);
+    // A subcommand in the Exec line (wezterm's `start`) is already part of
+    // the instance arguments
+    let desktop_args = match desktop_args.first() {
+        Some(first) if class_args.first() == Some(first) => &desktop_args[1..],
+        _ => desktop_args,
+    };
+    if options.remote_control {
+        class_args.extend(remote_control_args(command));
+    }
+    let app_ids = app_ids_for(command, desktop.as_ref());
+    let theme_args = options
+        .theme
+        .map(|theme| get_theme_args(command, theme))
+        .unwrap_or_default();
 
-    let mut cmd = Command::new(command);
+    let title_args = options
+        .title
+        .map(|title| get_title_args(command, title))
+        .unwrap_or_default();
+    let size_args = options
+        .size
+        .map(|(width, height)| get_size_args(command, width, height))
+        .unwrap_or_default();
+
+    let mut cmd = detached_command(command, options.launch_prefix, &options.detach);
+    // Theme flags go first: for wezterm they are global options that must
+    // precede the `start` options.
+    cmd.args(&theme_args);
     cmd.args(&class_args);
-    cmd.args(args);
+    cmd.args(&size_args);
+    cmd.args(&title_args);
+    cmd.args(desktop_args);
+    cmd.args(options.args);
+    if let Some(program) = terminal_program(
+        options.startup_commands,
+        options.shell,
+        options.tmux_session,
+    ) {
+        info!("Running {program:?} in the terminal");
+        cmd.args(exec_args(command, program));
+    }
+    if let Some(cwd) = options.cwd {
+        cmd.current_dir(cwd);
+    }
+    if options.login_env {
+        cmd.envs(login_env().iter().map(|(k, v)| (k, v)));
+    }
 
     info!(
-        "Spawning terminal: {} {:?} {:?} (tracking app_id={})",
-        command, class_args, args, app_id
+        "Spawning terminal: {:?} {} {:?} {:?} {:?} (tracking app_ids={:?}, detach={:?})",
+        options.launch_prefix,
+        command,
+        theme_args,
+        class_args,
+        options.args,
+        app_ids,
+        options.detach
     );
 
     match cmd.spawn() {
@@ -26,7 +182,11 @@ pub fn spawn_terminal(command: &str, args: &[String]) -> Option<SpawnResult> {
             // Intentionally drop the Child handle — the terminal process is
             // independent and will be reaped via waitpid when it exits.
             drop(child);
-            Some(SpawnResult { pid, app_id })
+            Some(SpawnResult {
+                pid,
+                start_time: process_start_time(pid),
+                app_ids,
+            })
         }
         Err(e) => {
             error!("Failed to spawn terminal '{}': {}", command, e);
@@ -35,21 +195,434 @@ pub fn spawn_terminal(command: &str, args: &[String]) -> Option<SpawnResult> {
     }
 }
 
-/// Returns the Wayland app_id that the given terminal will use.
-pub fn get_app_id(command: &str) -> String {
-    get_class_args(command).1
+/// A `Command` for `command`, run through `prefix`, set up to run detached
+/// from the daemon.
+fn detached_command(command: &str, prefix: &[String], detach: &DetachStrategy) -> Command {
+    let (program, prefix_args) = prefixed_program(command, prefix);
+    let plain = || {
+        let mut cmd = Command::new(program);
+        cmd.args(&prefix_args);
+        cmd
+    };
+    match detach {
+        DetachStrategy::None => plain(),
+        DetachStrategy::Setsid => {
+            let mut cmd = plain();
+            // SAFETY: setsid is async-signal-safe and touches no state of
+            // the parent process.
+            unsafe {
+                cmd.pre_exec(|| {
+                    nix::unistd::setsid()?;
+                    Ok(())
+                });
+            }
+            cmd
+        }
+        // systemd-run execs the command inside the scope, so the spawned
+        // PID is the terminal's
+        DetachStrategy::SystemdScope if is_installed("systemd-run") => {
+            let mut cmd = Command::new("systemd-run");
+            cmd.args(["--user", "--scope", "--quiet", "--collect"])
+                .arg(program)
+                .args(&prefix_args);
+            cmd
+        }
+        DetachStrategy::SystemdScope => {
+            warn!("systemd-run not found, spawning {command} without a scope");
+            plain()
+        }
+    }
+}
+
+/// The program to run and its first arguments: the prefix, which is given
+/// the terminal command, or else the terminal command itself. The
+/// terminal's own arguments follow, so the prefix never sees them as its
+/// options.
+fn prefixed_program<'a>(command: &'a str, prefix: &'a [String]) -> (&'a str, Vec<&'a str>) {
+    match prefix.split_first() {
+        Some((program, args)) => (
+            program,
+            args.iter().map(String::as_str).chain([command]).collect(),
+        ),
+        None => (command, Vec::new()),
+    }
+}
+
+/// Opens a new tab in `path` inside the running terminal, for terminals
+/// started with a remote-control socket. Returns false if the terminal has
+/// no such interface.
+pub fn open_tab(command: &str, path: &Path) -> bool {
+    let desktop = resolve_command(command);
+    let command = desktop
+        .as_ref()
+        .map_or(command, |entry| entry.command.as_str());
+    if command.rsplit('/').next() != Some("kitty") {
+        return false;
+    }
+    let Some(socket) = kitty_socket() else {
+        return false;
+    };
+
+    let mut cmd = Command::new(command);
+    cmd.args(["@", "--to", &socket, "launch", "--type=tab", "--cwd"])
+        .arg(path);
+
+    info!("Opening {} in a new kitty tab", path.display());
+    spawn_detached(cmd);
+    true
+}
+
+/// Whether the terminal's background opacity can be changed while it runs.
+pub fn supports_background_opacity(command: &str) -> bool {
+    let desktop = resolve_command(command);
+    let command = desktop
+        .as_ref()
+        .map_or(command, |entry| entry.command.as_str());
+    command.rsplit('/').next() == Some("kitty") && kitty_socket().is_some()
+}
+
+/// The command that sets the terminal's background opacity to `percent`,
+/// or None if the terminal has no runtime opacity control.
+pub fn background_opacity_command(command: &str, percent: u32) -> Option<Command> {
+    let desktop = resolve_command(command);
+    let command = desktop
+        .as_ref()
+        .map_or(command, |entry| entry.command.as_str());
+    if command.rsplit('/').next() != Some("kitty") {
+        return None;
+    }
+    let socket = kitty_socket()?;
+    let mut cmd = Command::new(command);
+    cmd.args(["@", "--to", &socket, "set-background-opacity"])
+        .arg(format!("{}", f64::from(percent) / 100.0));
+    Some(cmd)
+}
+
+/// Variables of the login shell's environment that describe the shell
+/// process itself rather than the session.
+const SHELL_LOCAL_VARS: &[&str] = &["_", "PWD", "OLDPWD", "SHLVL"];
+
+/// The environment of the user's login shell, which has the variables set
+/// in their profile that a daemon started by systemd lacks. The shell runs
+/// once; later calls return the cached result, or wait for the first call
+/// to finish. Empty if the shell fails.
+pub fn login_env() -> &'static [(String, String)] {
+    static LOGIN_ENV: OnceLock<Vec<(String, String)>> = OnceLock::new();
+    LOGIN_ENV.get_or_init(|| {
+        let shell = std::env::var("SHELL").unwrap_or_else(|_| String::from("/bin/sh"));
+        let output = Command::new(&shell)
+            .args(["-l", "-c", "env -0"])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                let env = parse_env(&output.stdout);
+                info!("Read {} variables from the login shell {shell}", env.len());
+                env
+            }
+            Ok(output) => {
+                warn!("Login shell {shell} failed with {}", output.status);
+                Vec::new()
+            }
+            Err(e) => {
+                warn!("Failed to run login shell {shell}: {e}");
+                Vec::new()
+            }
+        }
+    })
+}
+
+/// Parses the NUL-separated output of `env -0`.
+fn parse_env(output: &[u8]) -> Vec<(String, String)> {
+    output
+        .split(|b| *b == 0)
+        .filter_map(|entry| std::str::from_utf8(entry).ok()?.split_once('='))
+        .filter(|(name, _)| !name.is_empty() && !SHELL_LOCAL_VARS.contains(name))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Runs a helper command in the background, reaping it when it exits so it
+/// doesn't linger as a zombie.
+pub fn spawn_detached(mut cmd: Command) {
+    match cmd.spawn() {
+        Ok(mut child) => {
+            std::thread::spawn(move || {
+                let _ = child.wait();
+            });
+        }
+        Err(e) => error!("Failed to run {:?}: {}", cmd.get_program(), e),
+    }
+}
+
+/// Runs a user-configured hook through `sh -c` without waiting for it,
+/// with `env` added to its environment.
+pub fn run_hook(name: &str, command: &str, env: &[(&str, String)]) {
+    info!("Running {name} hook: {command} {env:?}");
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(k, v)| (k, v)));
+    spawn_detached(cmd);
+}
+
+/// Remote-control socket kitty is told to listen on, so tabs can be opened
+/// in the quake instance specifically.
+fn kitty_socket() -> Option<String> {
+    // Only the runtime dir is private to the user; a socket in /tmp could
+    // be taken over by anyone
+    let runtime_dir = std::env::var("XDG_RUNTIME_DIR")
+        .ok()
+        .filter(|dir| !dir.is_empty())?;
+    Some(format!("unix:{runtime_dir}/{QUAKE_APP_ID}-kitty"))
+}
+
+/// Arguments that make the terminal listen on its remote-control socket,
+/// for opening tabs and changing its opacity while it runs. Empty for
+/// terminals without one, or if there is no private place for the socket.
+fn remote_control_args(command: &str) -> Vec<String> {
+    let binary = command.rsplit('/').next().unwrap_or(command);
+    if binary != "kitty" {
+        return Vec::new();
+    }
+    let Some(socket) = kitty_socket() else {
+        warn!("XDG_RUNTIME_DIR is not set, starting kitty without remote control");
+        return Vec::new();
+    };
+    vec![
+        "--listen-on".into(),
+        socket,
+        "-o".into(),
+        "allow_remote_control=socket-only".into(),
+        "-o".into(),
+        "dynamic_background_opacity=yes".into(),
+    ]
+}
+
+/// Sends SIGTERM to `pid` after `delay`, unless it has exited by then.
+pub fn terminate_after(pid: u32, start_time: Option<u64>, delay: std::time::Duration) {
+    std::thread::spawn(move || terminate_within(pid, start_time, delay));
+}
+
+/// Waits up to `delay` for `pid` to exit, reaping it, and sends it SIGTERM
+/// if it is still running then.
+pub fn terminate_within(pid: u32, start_time: Option<u64>, delay: std::time::Duration) {
+    let deadline = std::time::Instant::now() + delay;
+    while std::time::Instant::now() < deadline {
+        if try_reap(pid) {
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    if is_alive(pid, start_time) {
+        info!("PID {pid} still running after its close command, sending SIGTERM");
+        let _ = nix::sys::signal::kill(
+            nix::unistd::Pid::from_raw(pid as i32),
+            nix::sys::signal::Signal::SIGTERM,
+        );
+    }
+}
+
+/// Reaps the child `pid` if it has exited. Returns false while it still
+/// runs, and true once it is gone, also when it is no longer our child.
+pub fn try_reap(pid: u32) -> bool {
+    use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+
+    match waitpid(
+        nix::unistd::Pid::from_raw(pid as i32),
+        Some(WaitPidFlag::WNOHANG),
+    ) {
+        Ok(WaitStatus::StillAlive) => false,
+        Ok(status) => {
+            tracing::debug!("Reaped PID {pid}: {status:?}");
+            true
+        }
+        Err(_) => true,
+    }
+}
+
+/// Reads the start time of `pid` (in clock ticks since boot) from
+/// `/proc/<pid>/stat`.
+pub fn process_start_time(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name may contain spaces and parentheses, so skip past the
+    // last ')'. The remaining fields start at field 3; starttime is field 22.
+    let fields = &stat[stat.rfind(')')? + 1..];
+    fields.split_whitespace().nth(19)?.parse().ok()
+}
+
+/// Whether `pid` still refers to the process we spawned. If the start time
+/// no longer matches, the PID was reused by an unrelated process.
+pub fn is_alive(pid: u32, start_time: Option<u64>) -> bool {
+    let exists = nix::sys::signal::kill(nix::unistd::Pid::from_raw(pid as i32), None).is_ok();
+    match start_time {
+        Some(expected) => exists && process_start_time(pid) == Some(expected),
+        None => exists,
+    }
+}
+
+/// Returns the Wayland app_ids the given terminal may use, the expected one
+/// first.
+pub fn get_app_ids(command: &str) -> Vec<String> {
+    let desktop = resolve_command(command);
+    let command = desktop
+        .as_ref()
+        .map_or(command, |entry| entry.command.as_str());
+    app_ids_for(command, desktop.as_ref())
+}
+
+fn app_ids_for(command: &str, desktop: Option<&DesktopTerminal>) -> Vec<String> {
+    let binary = command.rsplit('/').next().unwrap_or(command);
+    let mut app_ids = vec![get_class_args(command).1];
+    if let Some((_, alternates)) = ALTERNATE_APP_IDS.iter().find(|(b, _)| *b == binary) {
+        app_ids.extend(alternates.iter().map(|id| id.to_string()));
+    }
+    // In case the terminal ignores our class flag
+    if let Some(entry) = desktop {
+        if !app_ids.contains(&entry.app_id) {
+            app_ids.push(entry.app_id.clone());
+        }
+    }
+    app_ids
+}
+
+/// Resolves `desktop:<id>` commands through the desktop file. Returns None
+/// for plain commands and, with a warning, for unknown desktop ids, which
+/// are then run as they are.
+fn resolve_command(command: &str) -> Option<DesktopTerminal> {
+    let id = command.strip_prefix("desktop:")?;
+    let entry = resolve_desktop_entry(id);
+    if entry.is_none() {
+        warn!("No usable desktop file for '{id}', running '{command}' as a command");
+    }
+    entry
+}
+
+/// Looks up the desktop file `<id>.desktop` in the XDG data dirs and reads
+/// its Exec line and app_id.
+pub fn resolve_desktop_entry(id: &str) -> Option<DesktopTerminal> {
+    let id = id.strip_suffix(".desktop").unwrap_or(id);
+    let path = application_dirs()
+        .into_iter()
+        .map(|dir| dir.join(format!("{id}.desktop")))
+        .find(|path| path.is_file())?;
+    let contents = std::fs::read_to_string(&path).ok()?;
+    parse_desktop_entry(id, &contents)
+}
+
+/// `applications` directories of the XDG data dirs, most specific first.
+fn application_dirs() -> Vec<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")));
+    let data_dirs = std::env::var_os("XDG_DATA_DIRS")
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+
+    data_home
+        .into_iter()
+        .chain(std::env::split_paths(&data_dirs))
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// The app_id is the StartupWMClass if set, otherwise the desktop id, which
+/// GTK and Qt terminals use by default.
+fn parse_desktop_entry(id: &str, contents: &str) -> Option<DesktopTerminal> {
+    let mut in_entry = false;
+    let mut exec = None;
+    let mut wm_class = None;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=').filter(|_| in_entry) else {
+            continue;
+        };
+        match key.trim() {
+            "Exec" => exec = Some(value.trim()),
+            "StartupWMClass" => wm_class = Some(value.trim()),
+            _ => {}
+        }
+    }
+
+    let mut words = split_exec(exec?)
+        .into_iter()
+        .filter_map(|word| match word.as_str() {
+            "%%" => Some(String::from("%")),
+            // Field codes like %f or %U; nothing is passed for them
+            _ if word.len() == 2 && word.starts_with('%') => None,
+            _ => Some(word),
+        });
+    Some(DesktopTerminal {
+        command: words.next()?,
+        args: words.collect(),
+        app_id: wm_class.unwrap_or(id).to_string(),
+    })
+}
+
+/// Splits an Exec value into words, honoring double quotes and backslash
+/// escapes inside them.
+fn split_exec(exec: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            '\\' if quoted => word.extend(chars.next()),
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+/// Arguments that make `binary` start a standalone instance. They come
+/// first, as they may include a subcommand.
+fn instance_args(binary: &str) -> Vec<String> {
+    let mode = KNOWN_TERMINALS
+        .iter()
+        .find(|&&(cmd, _, _, _)| cmd == binary)
+        .map_or(InstanceMode::Standalone, |&(_, _, _, mode)| mode);
+
+    match mode {
+        InstanceMode::Standalone => Vec::new(),
+        InstanceMode::NoGtkSingleInstance => vec!["--gtk-single-instance=false".into()],
+        InstanceMode::AlwaysNewProcess => vec!["start".into(), "--always-new-process".into()],
+    }
 }
 
 fn get_class_args(command: &str) -> (Vec<String>, String) {
     let binary = command.rsplit('/').next().unwrap_or(command);
+    let (class_args, app_id) = get_app_id_args(binary);
+    let mut args = instance_args(binary);
+    args.extend(class_args);
+    (args, app_id)
+}
 
+fn get_app_id_args(binary: &str) -> (Vec<String>, String) {
     match binary {
         // ghostty on GTK ignores --class; it always uses its default app_id.
-        // Use --gtk-single-instance=false to avoid joining an existing instance.
-        "ghostty" => (
-            vec!["--gtk-single-instance=false".into()],
-            "com.mitchellh.ghostty".to_string(),
-        ),
+        "ghostty" => (Vec::new(), "com.mitchellh.ghostty".to_string()),
         // foot uses --app-id
         "foot" => (
             vec![format!("--app-id={QUAKE_APP_ID}")],
@@ -60,6 +633,11 @@ fn get_class_args(command: &str) -> (Vec<String>, String) {
             vec!["--class".into(), QUAKE_APP_ID.into()],
             QUAKE_APP_ID.to_string(),
         ),
+        // xterm runs under XWayland, where the X11 class becomes the app_id
+        "xterm" => (
+            vec!["-class".into(), QUAKE_APP_ID.into()],
+            QUAKE_APP_ID.to_string(),
+        ),
         // Default: try --class and hope it works
         _ => (
             vec!["--class".into(), QUAKE_APP_ID.into()],
@@ -67,3 +645,483 @@ fn get_class_args(command: &str) -> (Vec<String>, String) {
         ),
     }
 }
+
+/// Returns true if `command` is an existing path or can be found in `$PATH`.
+pub fn is_installed(command: &str) -> bool {
+    if command.contains('/') {
+        return Path::new(command).is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|paths| std::env::split_paths(&paths).any(|dir| dir.join(command).is_file()))
+}
+
+/// Returns true if `command`, or the program of its `desktop:<id>` entry,
+/// is installed.
+pub fn is_usable(command: &str) -> bool {
+    match command.strip_prefix("desktop:") {
+        Some(id) => resolve_desktop_entry(id).is_some_and(|entry| is_installed(&entry.command)),
+        None => is_installed(command),
+    }
+}
+
+/// Detects the user's preferred terminal from `$TERMINAL` or the
+/// xdg-terminal-exec preference lists. Only installed terminals are returned.
+pub fn detect_default_terminal() -> Option<String> {
+    if let Ok(terminal) = std::env::var("TERMINAL") {
+        if let Some(command) = terminal.split_whitespace().next() {
+            if is_installed(command) {
+                info!("Default terminal from $TERMINAL: {command}");
+                return Some(command.to_string());
+            }
+        }
+    }
+
+    for list in xdg_terminal_lists() {
+        let Ok(contents) = std::fs::read_to_string(&list) else {
+            continue;
+        };
+        for entry in contents.lines().map(str::trim) {
+            if entry.is_empty() || entry.starts_with('#') {
+                continue;
+            }
+            // Entries look like `com.system76.CosmicTerm.desktop[:action]`
+            let id = entry.split(':').next().unwrap_or(entry);
+            let id = id.strip_suffix(".desktop").unwrap_or(id);
+            let command = TERMINAL_DESKTOP_IDS
+                .iter()
+                .find(|&&(desktop_id, _)| desktop_id == id)
+                .map(|&(_, command)| command);
+            if let Some(command) = command.filter(|c| is_installed(c)) {
+                info!("Default terminal from {}: {command}", list.display());
+                return Some(command.to_string());
+            }
+        }
+    }
+
+    None
+}
+
+/// xdg-terminal-exec preference files, most specific first.
+fn xdg_terminal_lists() -> Vec<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")));
+    let desktops = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+
+    let mut lists = Vec::new();
+    for dir in config_home.into_iter().chain([PathBuf::from("/etc/xdg")]) {
+        for desktop in desktops.split(':').filter(|d| !d.is_empty()) {
+            lists.push(dir.join(format!("{}-xdg-terminals.list", desktop.to_lowercase())));
+        }
+        lists.push(dir.join("xdg-terminals.list"));
+    }
+    lists
+}
+
+/// What the terminal runs instead of its default shell: `shell`, or a
+/// `sh -c` script running `commands` in order and then starting the shell,
+/// so the terminal stays open in the environment they set up. Each command
+/// and `shell` are shell syntax already and the script is passed as a
+/// single argument, so nothing needs quoting.
+///
+/// With `tmux_session`, the terminal attaches to that tmux session instead,
+/// which runs the script or shell only when it creates the session.
+fn terminal_program(
+    commands: &[String],
+    shell: Option<&str>,
+    tmux_session: Option<&str>,
+) -> Option<Vec<String>> {
+    let script = (!commands.is_empty()).then(|| {
+        let mut script = commands.join("; ");
+        script.push_str("; exec ");
+        script.push_str(shell.unwrap_or("\"${SHELL:-/bin/sh}\""));
+        script
+    });
+    if let Some(session) = tmux_session {
+        let mut program: Vec<String> = ["tmux", "new-session", "-A", "-s", session]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        // tmux runs a single command argument through the shell itself
+        program.extend(script.or_else(|| shell.map(String::from)));
+        return Some(program);
+    }
+    match script {
+        Some(script) => Some(vec!["sh".into(), "-c".into(), script]),
+        None => shell.map(|shell| shell.split_whitespace().map(String::from).collect()),
+    }
+}
+
+/// Arguments that make the terminal run `program` instead of the default
+/// shell. They go last, after all options.
+fn exec_args(command: &str, program: Vec<String>) -> Vec<String> {
+    let binary = command.rsplit('/').next().unwrap_or(command);
+
+    let mut args: Vec<String> = match binary {
+        // The program is positional
+        "kitty" | "foot" => Vec::new(),
+        "wezterm" => vec!["--".into()],
+        _ => vec!["-e".into()],
+    };
+    args.extend(program);
+    args
+}
+
+/// Flags setting the initial window size in pixels. Terminals that only
+/// take a size in cells are left alone, as the cell size isn't known here.
+fn get_size_args(command: &str, width: i32, height: i32) -> Vec<String> {
+    let binary = command.rsplit('/').next().unwrap_or(command);
+
+    match binary {
+        "foot" => vec![format!("--window-size-pixels={width}x{height}")],
+        "kitty" => vec![
+            "-o".into(),
+            format!("initial_window_width={width}"),
+            "-o".into(),
+            format!("initial_window_height={height}"),
+            "-o".into(),
+            "remember_window_size=no".into(),
+        ],
+        _ => {
+            info!("{binary} has no pixel size flag, leaving its size alone");
+            Vec::new()
+        }
+    }
+}
+
+/// Whether `command` is started with a title flag, see [`get_title_args`].
+pub fn has_title_flag(command: &str) -> bool {
+    let desktop = resolve_command(command);
+    let command = desktop
+        .as_ref()
+        .map_or(command, |entry| entry.command.as_str());
+    matches!(
+        command.rsplit('/').next(),
+        Some("alacritty" | "kitty" | "foot" | "ghostty")
+    )
+}
+
+/// A title no other window carries, to recognize the terminal's window by.
+pub fn title_token() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.subsec_nanos());
+    format!("{QUAKE_APP_ID}-{:x}-{nanos:x}", std::process::id())
+}
+
+fn get_title_args(command: &str, title: &str) -> Vec<String> {
+    let binary = command.rsplit('/').next().unwrap_or(command);
+
+    match binary {
+        "alacritty" | "kitty" => vec!["--title".into(), title.into()],
+        "foot" | "ghostty" => vec![format!("--title={title}")],
+        _ => {
+            info!("{binary} has no title flag, ignoring window title '{title}'");
+            Vec::new()
+        }
+    }
+}
+
+/// Returns the arguments that apply `theme` for the given terminal. The
+/// theme is a config file to include, or a theme name where the terminal
+/// has built-in themes (wezterm, ghostty).
+fn get_theme_args(command: &str, theme: &str) -> Vec<String> {
+    let binary = command.rsplit('/').next().unwrap_or(command);
+
+    match binary {
+        "alacritty" => vec!["-o".into(), format!("general.import=[\"{theme}\"]")],
+        "kitty" => vec!["-o".into(), format!("include={theme}")],
+        "foot" => vec![format!("--override=include={theme}")],
+        "wezterm" => vec!["--config".into(), format!("color_scheme=\"{theme}\"")],
+        "ghostty" => vec![format!("--theme={theme}")],
+        _ => {
+            info!("{binary} has no theme flag, ignoring theme '{theme}'");
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ghostty_disables_single_instance_and_keeps_its_app_id() {
+        assert_eq!(
+            get_class_args("ghostty"),
+            (
+                vec![String::from("--gtk-single-instance=false")],
+                String::from("com.mitchellh.ghostty")
+            )
+        );
+    }
+
+    #[test]
+    fn foot_uses_app_id_flag() {
+        assert_eq!(
+            get_class_args("foot"),
+            (
+                vec![format!("--app-id={QUAKE_APP_ID}")],
+                QUAKE_APP_ID.to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn class_flag_terminals() {
+        for command in ["cosmic-term", "alacritty", "kitty"] {
+            assert_eq!(
+                get_class_args(command),
+                (
+                    vec![String::from("--class"), QUAKE_APP_ID.to_string()],
+                    QUAKE_APP_ID.to_string()
+                ),
+                "{command}"
+            );
+        }
+    }
+
+    #[test]
+    fn wezterm_starts_a_new_process() {
+        assert_eq!(
+            get_class_args("wezterm"),
+            (
+                vec![
+                    String::from("start"),
+                    String::from("--always-new-process"),
+                    String::from("--class"),
+                    QUAKE_APP_ID.to_string()
+                ],
+                QUAKE_APP_ID.to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn every_known_terminal_gets_a_trackable_window() {
+        for &(command, _, _, mode) in KNOWN_TERMINALS {
+            let (args, app_id) = get_class_args(command);
+            match mode {
+                InstanceMode::Standalone => {}
+                InstanceMode::NoGtkSingleInstance => {
+                    assert_eq!(args[0], "--gtk-single-instance=false", "{command}");
+                }
+                InstanceMode::AlwaysNewProcess => {
+                    assert_eq!(&args[..2], ["start", "--always-new-process"], "{command}");
+                }
+            }
+            // Either our class is set or the terminal's fixed app_id is known
+            assert!(
+                args.iter().any(|arg| arg.contains(QUAKE_APP_ID)) || app_id != QUAKE_APP_ID,
+                "{command}: {args:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn startup_commands_run_in_order_before_the_shell() {
+        let commands = [
+            String::from("cd ~/src"),
+            String::from(". .venv/bin/activate"),
+        ];
+        assert_eq!(terminal_program(&[], None, None), None);
+        assert_eq!(
+            terminal_program(&commands, None, None),
+            Some(vec![
+                String::from("sh"),
+                String::from("-c"),
+                String::from("cd ~/src; . .venv/bin/activate; exec \"${SHELL:-/bin/sh}\"")
+            ])
+        );
+        assert_eq!(
+            terminal_program(&commands, Some("fish"), None).map(|program| program[2].clone()),
+            Some(String::from("cd ~/src; . .venv/bin/activate; exec fish"))
+        );
+    }
+
+    #[test]
+    fn shell_runs_directly_without_startup_commands() {
+        assert_eq!(
+            terminal_program(&[], Some("nu --login"), None),
+            Some(vec![String::from("nu"), String::from("--login")])
+        );
+    }
+
+    #[test]
+    fn launch_prefix_runs_the_terminal() {
+        assert_eq!(prefixed_program("kitty", &[]), ("kitty", vec![]));
+        let prefix = [String::from("nice"), String::from("-n"), String::from("10")];
+        assert_eq!(
+            prefixed_program("kitty", &prefix),
+            ("nice", vec!["-n", "10", "kitty"])
+        );
+    }
+
+    #[test]
+    fn try_reap_reaps_only_exited_children() {
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+        assert!(!try_reap(child.id()));
+        child.kill().unwrap();
+        let reaped = (0..100).any(|_| {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            try_reap(child.id())
+        });
+        assert!(reaped);
+    }
+
+    #[test]
+    fn login_env_keeps_session_variables() {
+        assert_eq!(
+            parse_env(b"PATH=/usr/bin:/home/me/.local/bin\0SHLVL=2\0EDITOR=hx\0JSON={\"a\":1}\0"),
+            vec![
+                (
+                    String::from("PATH"),
+                    String::from("/usr/bin:/home/me/.local/bin")
+                ),
+                (String::from("EDITOR"), String::from("hx")),
+                (String::from("JSON"), String::from("{\"a\":1}")),
+            ]
+        );
+    }
+
+    #[test]
+    fn tmux_session_wraps_the_shell_and_startup_commands() {
+        let tmux = ["tmux", "new-session", "-A", "-s", "quake"].map(String::from);
+        assert_eq!(
+            terminal_program(&[], None, Some("quake")),
+            Some(tmux.to_vec())
+        );
+        assert_eq!(
+            terminal_program(&[], Some("nu --login"), Some("quake")),
+            Some([&tmux[..], &[String::from("nu --login")]].concat())
+        );
+        assert_eq!(
+            terminal_program(&[String::from("cd ~/src")], Some("fish"), Some("quake")),
+            Some([&tmux[..], &[String::from("cd ~/src; exec fish")]].concat())
+        );
+    }
+
+    #[test]
+    fn program_uses_each_terminals_exec_syntax() {
+        let program = || vec![String::from("fish")];
+        assert_eq!(exec_args("alacritty", program()), ["-e", "fish"]);
+        assert_eq!(exec_args("foot", program()), ["fish"]);
+        assert_eq!(exec_args("wezterm", program()), ["--", "fish"]);
+    }
+
+    #[test]
+    fn size_flags_are_in_pixels() {
+        assert_eq!(
+            get_size_args("foot", 1920, 432),
+            ["--window-size-pixels=1920x432"]
+        );
+        assert!(
+            get_size_args("kitty", 1920, 432).contains(&String::from("initial_window_height=432"))
+        );
+        assert!(get_size_args("alacritty", 1920, 432).is_empty());
+    }
+
+    #[test]
+    fn title_is_one_argument_even_with_spaces() {
+        assert_eq!(
+            get_title_args("alacritty", "Quake Terminal"),
+            ["--title", "Quake Terminal"]
+        );
+        assert_eq!(
+            get_title_args("ghostty", "Quake Terminal"),
+            ["--title=Quake Terminal"]
+        );
+        assert!(get_title_args("cosmic-term", "Quake Terminal").is_empty());
+    }
+
+    #[test]
+    fn only_kitty_listens_for_remote_control() {
+        let args = remote_control_args("/usr/bin/kitty");
+        match kitty_socket() {
+            Some(socket) => assert_eq!(&args[..2], [String::from("--listen-on"), socket]),
+            None => assert!(args.is_empty()),
+        }
+        assert!(remote_control_args("wezterm").is_empty());
+    }
+
+    #[test]
+    fn unknown_terminal_falls_back_to_class() {
+        assert_eq!(
+            get_class_args("rio"),
+            (
+                vec![String::from("--class"), QUAKE_APP_ID.to_string()],
+                QUAKE_APP_ID.to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn xterm_uses_x11_class_flag() {
+        assert_eq!(
+            get_class_args("xterm"),
+            (
+                vec![String::from("-class"), QUAKE_APP_ID.to_string()],
+                QUAKE_APP_ID.to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn path_prefixed_command_matches_binary_name() {
+        assert_eq!(
+            get_class_args("/usr/bin/alacritty"),
+            get_class_args("alacritty")
+        );
+        assert_eq!(
+            get_class_args("/usr/bin/ghostty"),
+            get_class_args("ghostty")
+        );
+    }
+
+    #[test]
+    fn app_ids_include_alternates_after_expected_one() {
+        assert_eq!(
+            get_app_ids("/usr/bin/ghostty"),
+            vec![
+                String::from("com.mitchellh.ghostty"),
+                String::from("com.mitchellh.ghostty-debug")
+            ]
+        );
+        assert_eq!(get_app_ids("foot"), vec![QUAKE_APP_ID.to_string()]);
+    }
+
+    #[test]
+    fn desktop_entry_exec_and_app_id() {
+        let contents = "[Desktop Entry]\n\
+            Name=WezTerm\n\
+            Exec=wezterm start --cwd . %U\n\
+            StartupWMClass=org.wezfurlong.wezterm\n\
+            [Desktop Action new-window]\n\
+            Exec=wezterm start --always-new-process\n";
+        assert_eq!(
+            parse_desktop_entry("org.wezfurlong.wezterm", contents),
+            Some(DesktopTerminal {
+                command: String::from("wezterm"),
+                args: vec![
+                    String::from("start"),
+                    String::from("--cwd"),
+                    String::from(".")
+                ],
+                app_id: String::from("org.wezfurlong.wezterm"),
+            })
+        );
+    }
+
+    #[test]
+    fn desktop_entry_app_id_defaults_to_id() {
+        let contents = "[Desktop Entry]\nExec=\"/opt/my term/bin/term\" --single \"a \\\"b\\\"\"\n";
+        assert_eq!(
+            parse_desktop_entry("com.example.Term", contents),
+            Some(DesktopTerminal {
+                command: String::from("/opt/my term/bin/term"),
+                args: vec![String::from("--single"), String::from("a \"b\"")],
+                app_id: String::from("com.example.Term"),
+            })
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use futures::StreamExt;
+use serde::Serialize;
+
+use crate::config::QuakeConfig;
+
+/// Well-known bus name of the stats object. The application's own name is
+/// owned by libcosmic's single-instance connection.
+const BUS_NAME: &str = "com.github.m0rf30.CosmicExtQuakeTerminal.Stats";
+const OBJECT_PATH: &str = "/com/github/m0rf30/CosmicExtQuakeTerminal/Stats";
+const INTERFACE: &str = "com.github.m0rf30.CosmicExtQuakeTerminal.Stats";
+
+/// Counters for debugging flaky behavior, readable over D-Bus.
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    pub toggles: AtomicU64,
+    pub spawns: AtomicU64,
+    pub spawn_failures: AtomicU64,
+    pub windows_adopted: AtomicU64,
+    /// Restarts of the Wayland loop after it panicked or lost its
+    /// connection
+    pub controller_restarts: AtomicU64,
+    /// Milliseconds from the last show or hide command until the compositor
+    /// confirmed it; 0 until one is measured
+    pub show_latency_ms: AtomicU64,
+    pub hide_latency_ms: AtomicU64,
+    /// The config the daemon runs with, as pretty JSON
+    config: Mutex<String>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            toggles: AtomicU64::new(0),
+            spawns: AtomicU64::new(0),
+            spawn_failures: AtomicU64::new(0),
+            windows_adopted: AtomicU64::new(0),
+            controller_restarts: AtomicU64::new(0),
+            show_latency_ms: AtomicU64::new(0),
+            hide_latency_ms: AtomicU64::new(0),
+            config: Mutex::new(String::new()),
+        }
+    }
+}
+
+impl Stats {
+    /// Records the config in effect, overrides and corrections included.
+    pub fn set_config(&self, config: &QuakeConfig) {
+        match serde_json::to_string_pretty(config) {
+            Ok(json) => *self.config.lock().unwrap() = json,
+            Err(e) => tracing::warn!("Failed to serialize config: {e}"),
+        }
+    }
+}
+
+/// Reply of `GetStats`, signature `(tttttttt)`.
+#[derive(Debug, Serialize, zbus::zvariant::Type)]
+struct StatsReply {
+    uptime_secs: u64,
+    toggles: u64,
+    spawns: u64,
+    spawn_failures: u64,
+    windows_adopted: u64,
+    controller_restarts: u64,
+    show_latency_ms: u64,
+    hide_latency_ms: u64,
+}
+
+struct StatsInterface {
+    stats: Arc<Stats>,
+}
+
+#[zbus::interface(name = "com.github.m0rf30.CosmicExtQuakeTerminal.Stats")]
+impl StatsInterface {
+    fn get_stats(&self) -> StatsReply {
+        let stats = &self.stats;
+        StatsReply {
+            uptime_secs: stats.started.elapsed().as_secs(),
+            toggles: stats.toggles.load(Ordering::Relaxed),
+            spawns: stats.spawns.load(Ordering::Relaxed),
+            spawn_failures: stats.spawn_failures.load(Ordering::Relaxed),
+            windows_adopted: stats.windows_adopted.load(Ordering::Relaxed),
+            controller_restarts: stats.controller_restarts.load(Ordering::Relaxed),
+            show_latency_ms: stats.show_latency_ms.load(Ordering::Relaxed),
+            hide_latency_ms: stats.hide_latency_ms.load(Ordering::Relaxed),
+        }
+    }
+
+    fn get_config(&self) -> String {
+        self.stats.config.lock().unwrap().clone()
+    }
+}
+
+/// Asks the running daemon for the config it runs with, as pretty JSON.
+pub fn fetch_config() -> Result<String, String> {
+    let connection =
+        zbus::blocking::Connection::session().map_err(|e| format!("No session bus: {e}"))?;
+    let reply = connection
+        .call_method(
+            Some(BUS_NAME),
+            OBJECT_PATH,
+            Some(INTERFACE),
+            "GetConfig",
+            &(),
+        )
+        .map_err(|e| format!("The daemon is not running or didn't answer: {e}"))?;
+    reply
+        .body()
+        .deserialize::<String>()
+        .map_err(|e| format!("Unexpected reply from the daemon: {e}"))
+}
+
+/// Serves `stats` on the session bus for as long as the subscription runs.
+/// It never produces messages.
+pub fn subscription<M: Send + 'static>(stats: Arc<Stats>) -> cosmic::iced::Subscription<M> {
+    struct StatsSub;
+
+    cosmic::iced::Subscription::run_with_id(
+        std::any::TypeId::of::<StatsSub>(),
+        futures::stream::once(async move {
+            let connection = zbus::connection::Builder::session()
+                .and_then(|builder| builder.name(BUS_NAME))
+                .and_then(|builder| builder.serve_at(OBJECT_PATH, StatsInterface { stats }));
+            match connection {
+                Ok(builder) => match builder.build().await {
+                    // Keep the connection alive
+                    Ok(_connection) => futures::future::pending::<()>().await,
+                    Err(e) => tracing::warn!("Failed to serve stats on D-Bus: {e}"),
+                },
+                Err(e) => tracing::warn!("Failed to serve stats on D-Bus: {e}"),
+            }
+        })
+        .filter_map(|()| async { None }),
+    )
+}
@@ -1,40 +1,155 @@
 use std::os::unix::io::AsFd;
 use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
 use cosmic_client_toolkit::toplevel_info::{ToplevelInfoHandler, ToplevelInfoState};
 use cosmic_client_toolkit::toplevel_management::{ToplevelManagerHandler, ToplevelManagerState};
+use cosmic_client_toolkit::workspace::{Workspace, WorkspaceHandler, WorkspaceState};
 use cosmic_protocols::toplevel_info::v1::client::zcosmic_toplevel_handle_v1::{
     self, ZcosmicToplevelHandleV1,
 };
 use cosmic_protocols::toplevel_management::v1::client::zcosmic_toplevel_manager_v1;
 use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use smithay_client_toolkit::output::{OutputHandler, OutputState};
 use smithay_client_toolkit::registry::{ProvidesRegistryState, RegistryState};
 use smithay_client_toolkit::seat::{Capability, SeatHandler, SeatState};
 use tokio::sync::mpsc as tokio_mpsc;
-use wayland_client::globals::registry_queue_init;
+use wayland_client::globals::{registry_queue_init, GlobalList};
+use wayland_client::protocol::wl_output::WlOutput;
 use wayland_client::protocol::wl_seat::WlSeat;
-use wayland_client::{Connection, QueueHandle, WEnum};
+use wayland_client::{Connection, Proxy, QueueHandle, WEnum};
 use wayland_protocols::ext::foreign_toplevel_list::v1::client::ext_foreign_toplevel_handle_v1::ExtForeignToplevelHandleV1;
+use wayland_protocols::ext::workspace::v1::client::ext_workspace_handle_v1::{
+    self, ExtWorkspaceHandleV1,
+};
+
+use crate::app::APP_ID;
+use crate::config::{HideStrategy, Monitor};
+use crate::geometry::Rect;
+
+/// How long the compositor gets to report our window activated before it
+/// is activated again.
+const ACTIVATION_TIMEOUT: Duration = Duration::from_millis(300);
 
 #[derive(Debug, Clone)]
 pub enum ToplevelEvent {
     Ready(WaylandController),
-    Found,
+    /// The loop connected and completed its first roundtrip
+    Connected,
+    /// Reply to [`WaylandController::report_focused_output`]
+    FocusedOutput(Option<OutputInfo>),
+    /// The focused window is now on the output with this name
+    FocusMoved(String),
+    /// An output appeared or its mode/scale changed
+    OutputChanged(OutputInfo),
+    OutputRemoved(String),
+    /// Our window appeared, with this app_id
+    Found(String),
+    /// Activate was skipped because a fullscreen window is focused and
+    /// raising over it is disabled
+    ActivationBlocked,
+    /// The terminal was activated; carries the output it is on
+    Shown(Option<OutputInfo>),
     Minimized,
     Activated,
     Deactivated,
     Closed,
+    /// The set of workspaces or their state changed
+    Workspaces(Vec<WorkspaceInfo>),
+    /// The open toplevels changed
+    Toplevels(Vec<ToplevelSummary>),
+    /// A rescan found no matching window; carries the app_ids of all open
+    /// windows
+    ScanMissed(Vec<String>),
+    /// Reply to [`WaylandController::reset_tracking`]: whether the window
+    /// found again is hidden, or None if no window was found
+    TrackingReset(Option<bool>),
+    /// The Wayland loop panicked; the controller no longer works
+    ControllerLost,
+    /// The Wayland connection failed or dropped, e.g. because the
+    /// compositor restarted; the controller no longer works
+    Disconnected,
 }
 
 #[derive(Debug, Clone)]
+pub struct OutputInfo {
+    pub name: String,
+    /// Logical position and size of the output
+    pub rect: Rect,
+    pub scale: i32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspaceInfo {
+    pub name: String,
+    /// 1-based position within its group, as used by `MoveToWorkspace`
+    pub number: usize,
+    /// Names of the outputs the workspace's group is on
+    pub outputs: Vec<String>,
+    pub active: bool,
+}
+
+/// A toplevel as listed in the settings window for troubleshooting.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToplevelSummary {
+    pub app_id: String,
+    pub title: String,
+    /// Whether it is the adopted terminal window
+    pub ours: bool,
+}
+
+/// Config-derived settings the Wayland loop needs to act on commands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WaylandOptions {
+    pub raise_over_fullscreen: bool,
+    /// App_ids that are never adopted, even if they are candidates
+    pub ignore_app_ids: Vec<String>,
+    /// Output the terminal is shown on
+    pub monitor: Monitor,
+}
+
+impl Default for WaylandOptions {
+    fn default() -> Self {
+        Self {
+            raise_over_fullscreen: true,
+            ignore_app_ids: Vec::new(),
+            monitor: Monitor::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum WaylandCommand {
     Minimize,
+    MoveToWorkspace(u32),
     Activate,
+    /// Activate, first moving the window to the focused output
+    ActivateHere,
+    Close,
+    ReportFocusedOutput,
+    SetOptions(WaylandOptions),
+    /// Replace the candidate app_ids and try to adopt an existing window
+    Rescan(Vec<String>),
+    /// Replace the candidate app_ids, e.g. after switching terminals
+    SetTargets(Vec<String>),
+    /// Forget our window and adopt one again by the current app_ids
+    ResetTracking,
+    /// Watch for a window carrying this unique title, which the terminal
+    /// was started with
+    WatchTitle(Option<String>),
+    /// Adopt the window that carried the watched title if none was found by
+    /// app_id, i.e. the terminal ignored its class flag
+    AdoptTitled,
+    /// Leave the event loop, closing the Wayland connection
+    Shutdown,
 }
 
 #[derive(Debug, Clone)]
 pub struct WaylandController {
     cmd_tx: std_mpsc::Sender<WaylandCommand>,
+    thread: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
 
 impl WaylandController {
@@ -42,9 +157,62 @@ impl WaylandController {
         let _ = self.cmd_tx.send(WaylandCommand::Minimize);
     }
 
+    pub fn hide(&self, strategy: &HideStrategy) {
+        let cmd = match strategy {
+            HideStrategy::Minimize => WaylandCommand::Minimize,
+            HideStrategy::MoveToWorkspace(n) => WaylandCommand::MoveToWorkspace(*n),
+        };
+        let _ = self.cmd_tx.send(cmd);
+    }
+
     pub fn activate(&self) {
         let _ = self.cmd_tx.send(WaylandCommand::Activate);
     }
+
+    pub fn activate_here(&self) {
+        let _ = self.cmd_tx.send(WaylandCommand::ActivateHere);
+    }
+
+    pub fn close(&self) {
+        let _ = self.cmd_tx.send(WaylandCommand::Close);
+    }
+
+    pub fn set_options(&self, options: WaylandOptions) {
+        let _ = self.cmd_tx.send(WaylandCommand::SetOptions(options));
+    }
+
+    pub fn report_focused_output(&self) {
+        let _ = self.cmd_tx.send(WaylandCommand::ReportFocusedOutput);
+    }
+
+    pub fn rescan(&self, app_ids: Vec<String>) {
+        let _ = self.cmd_tx.send(WaylandCommand::Rescan(app_ids));
+    }
+
+    pub fn set_targets(&self, app_ids: Vec<String>) {
+        let _ = self.cmd_tx.send(WaylandCommand::SetTargets(app_ids));
+    }
+
+    pub fn reset_tracking(&self) {
+        let _ = self.cmd_tx.send(WaylandCommand::ResetTracking);
+    }
+
+    pub fn watch_title(&self, title: Option<String>) {
+        let _ = self.cmd_tx.send(WaylandCommand::WatchTitle(title));
+    }
+
+    pub fn adopt_titled(&self) {
+        let _ = self.cmd_tx.send(WaylandCommand::AdoptTitled);
+    }
+
+    /// Stops the Wayland loop and waits for its thread to finish.
+    pub fn shutdown(&self) {
+        let _ = self.cmd_tx.send(WaylandCommand::Shutdown);
+        let thread = self.thread.lock().ok().and_then(|mut thread| thread.take());
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+    }
 }
 
 struct WaylandState {
@@ -53,12 +221,196 @@ struct WaylandState {
     toplevel_manager: Option<ToplevelManagerState>,
     seat_state: SeatState,
     seat: Option<WlSeat>,
-    target_app_id: String,
+    /// Whether the missing-seat warning was already logged
+    warned_no_seat: bool,
+    output_state: OutputState,
+    workspace_state: WorkspaceState,
+    /// App_ids accepted for the terminal window
+    target_app_ids: Vec<String>,
+    /// Unique title the terminal was last started with
+    title_token: Option<String>,
+    /// A window that carried `title_token`, kept as a fallback until it
+    /// closes
+    titled: Option<ExtForeignToplevelHandleV1>,
+    options: WaylandOptions,
     our_handle: Option<ZcosmicToplevelHandleV1>,
     our_foreign_handle: Option<ExtForeignToplevelHandleV1>,
     event_tx: tokio_mpsc::UnboundedSender<ToplevelEvent>,
     last_minimized: Option<bool>,
     last_activated: Option<bool>,
+    /// Workspaces last reported to the app
+    workspaces: Vec<WorkspaceInfo>,
+    /// Toplevels last reported to the app
+    toplevels: Vec<ToplevelSummary>,
+    /// Output of the focused window last reported to the app
+    focused_output: Option<String>,
+    /// Set while our window is parked on another workspace by the
+    /// `MoveToWorkspace` hide strategy.
+    parked: bool,
+    /// When to activate our window again if the compositor hasn't reported
+    /// it activated by then
+    activation_pending: Option<Instant>,
+}
+
+impl WaylandState {
+    /// Starts tracking `toplevel` if nothing is adopted yet and its app_id
+    /// is one of the target candidates.
+    fn try_adopt(&mut self, toplevel: &ExtForeignToplevelHandleV1) {
+        if self.our_foreign_handle.is_some() {
+            return;
+        }
+        let Some(info) = self.toplevel_info.info(toplevel) else {
+            return;
+        };
+        let Some(candidate) = match_app_id(
+            &info.app_id,
+            &self.target_app_ids,
+            &self.options.ignore_app_ids,
+        ) else {
+            return;
+        };
+        // With a fixed monitor, a candidate already shown on another output
+        // is some other window of the terminal. A fresh window has no
+        // output yet when it first matches, so it is still adopted.
+        if let Monitor::Named(ref name) = self.options.monitor {
+            let elsewhere = named_output(self, name)
+                .is_some_and(|target| !info.output.is_empty() && !info.output.contains(&target));
+            if elsewhere {
+                tracing::info!("Not adopting {} on another output than {name}", info.app_id);
+                return;
+            }
+        }
+
+        tracing::info!(
+            "Found our toplevel: app_id={} (candidate {} of {})",
+            info.app_id,
+            candidate + 1,
+            self.target_app_ids.len()
+        );
+        self.adopt(toplevel);
+    }
+
+    /// Adopts the window that carried the title token, if nothing is
+    /// adopted yet.
+    fn adopt_titled(&mut self) {
+        if self.our_foreign_handle.is_some() {
+            return;
+        }
+        let Some(toplevel) = self.titled.clone() else {
+            return;
+        };
+        if let Some(info) = self.toplevel_info.info(&toplevel) {
+            tracing::warn!(
+                "Window {} carried our title but not our app_id; the terminal ignored \
+                 its class flag, tracking the window by its title",
+                info.app_id
+            );
+        }
+        self.adopt(&toplevel);
+    }
+
+    /// Tracks `toplevel` as our window.
+    fn adopt(&mut self, toplevel: &ExtForeignToplevelHandleV1) {
+        let Some(info) = self.toplevel_info.info(toplevel) else {
+            return;
+        };
+        self.our_handle = info.cosmic_toplevel.clone();
+        self.our_foreign_handle = Some(toplevel.clone());
+        self.last_minimized = None;
+        self.last_activated = None;
+        let _ = self
+            .event_tx
+            .send(ToplevelEvent::Found(info.app_id.clone()));
+    }
+
+    /// Remembers `toplevel` if it carries the title token. Shells soon
+    /// replace the title, so it is caught as it changes.
+    fn note_title(&mut self, toplevel: &ExtForeignToplevelHandleV1) {
+        if self.titled.is_some() {
+            return;
+        }
+        let Some(info) = self.toplevel_info.info(toplevel) else {
+            return;
+        };
+        if matches_title(
+            &info.app_id,
+            &info.title,
+            self.title_token.as_deref(),
+            &self.options.ignore_app_ids,
+        ) {
+            tracing::debug!("Window {} carries our title", info.app_id);
+            self.titled = Some(toplevel.clone());
+        }
+    }
+}
+
+impl WaylandState {
+    /// Sends the open toplevels to the app if they changed, leaving out
+    /// `closed`, which is still listed while its close is handled.
+    fn report_toplevels(&mut self, closed: Option<&ExtForeignToplevelHandleV1>) {
+        let toplevels: Vec<ToplevelSummary> = self
+            .toplevel_info
+            .toplevels()
+            .filter(|info| Some(&info.foreign_toplevel) != closed)
+            .map(|info| ToplevelSummary {
+                app_id: info.app_id.clone(),
+                title: info.title.clone(),
+                ours: self.our_foreign_handle.as_ref() == Some(&info.foreign_toplevel),
+            })
+            .collect();
+        if toplevels != self.toplevels {
+            self.toplevels = toplevels.clone();
+            let _ = self.event_tx.send(ToplevelEvent::Toplevels(toplevels));
+        }
+    }
+
+    /// Tells the app when the focused window moved to another output.
+    fn report_focus(&mut self) {
+        let name = self
+            .toplevel_info
+            .toplevels()
+            .find(|info| {
+                info.state
+                    .contains(&zcosmic_toplevel_handle_v1::State::Activated)
+            })
+            .and_then(|info| info.output.iter().next())
+            .and_then(|output| self.output_state.info(output))
+            .and_then(|info| info.name);
+        if let Some(name) = name.filter(|name| self.focused_output.as_ref() != Some(name)) {
+            self.focused_output = Some(name.clone());
+            let _ = self.event_tx.send(ToplevelEvent::FocusMoved(name));
+        }
+    }
+}
+
+/// Index of `app_id` among the target candidates. Our own windows (the
+/// settings window) and ignored app_ids never match.
+fn match_app_id(app_id: &str, targets: &[String], ignored: &[String]) -> Option<usize> {
+    if app_id == APP_ID || ignored.iter().any(|id| id == app_id) {
+        return None;
+    }
+    targets
+        .iter()
+        .position(|id| id == app_id)
+        .or_else(|| match_x11_class(app_id, targets))
+}
+
+/// Whether a window carries the unique title the terminal was started with.
+fn matches_title(app_id: &str, title: &str, token: Option<&str>, ignored: &[String]) -> bool {
+    token.is_some_and(|token| token == title)
+        && app_id != APP_ID
+        && !ignored.iter().any(|id| id == app_id)
+}
+
+/// Matches an XWayland window whose app_id is its whole WM_CLASS, an
+/// instance and class pair separated by a NUL or a space, against either
+/// half. X11 classes are conventionally capitalized (xterm's is `XTerm`),
+/// so the halves are compared ignoring case.
+fn match_x11_class(app_id: &str, targets: &[String]) -> Option<usize> {
+    let (instance, class) = app_id.split_once(['\0', ' '])?;
+    targets.iter().position(|id| {
+        id.eq_ignore_ascii_case(instance.trim()) || id.eq_ignore_ascii_case(class.trim())
+    })
 }
 
 impl ProvidesRegistryState for WaylandState {
@@ -118,6 +470,44 @@ impl SeatHandler for WaylandState {
     fn remove_seat(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, _seat: WlSeat) {}
 }
 
+impl OutputHandler for WaylandState {
+    fn output_state(&mut self) -> &mut OutputState {
+        &mut self.output_state
+    }
+
+    fn new_output(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, output: WlOutput) {
+        if let Some(info) = output_info(self, &output) {
+            let _ = self.event_tx.send(ToplevelEvent::OutputChanged(info));
+        }
+    }
+
+    fn update_output(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, output: WlOutput) {
+        if let Some(info) = output_info(self, &output) {
+            let _ = self.event_tx.send(ToplevelEvent::OutputChanged(info));
+        }
+    }
+
+    fn output_destroyed(&mut self, _conn: &Connection, _qh: &QueueHandle<Self>, output: WlOutput) {
+        if let Some(info) = output_info(self, &output) {
+            let _ = self.event_tx.send(ToplevelEvent::OutputRemoved(info.name));
+        }
+    }
+}
+
+impl WorkspaceHandler for WaylandState {
+    fn workspace_state(&mut self) -> &mut WorkspaceState {
+        &mut self.workspace_state
+    }
+
+    fn done(&mut self) {
+        let workspaces = workspace_list(self);
+        if workspaces != self.workspaces {
+            self.workspaces = workspaces.clone();
+            let _ = self.event_tx.send(ToplevelEvent::Workspaces(workspaces));
+        }
+    }
+}
+
 impl ToplevelInfoHandler for WaylandState {
     fn toplevel_info_state(&mut self) -> &mut ToplevelInfoState {
         &mut self.toplevel_info
@@ -129,16 +519,10 @@ impl ToplevelInfoHandler for WaylandState {
         _qh: &QueueHandle<Self>,
         toplevel: &ExtForeignToplevelHandleV1,
     ) {
-        if let Some(info) = self.toplevel_info.info(toplevel) {
-            if info.app_id == self.target_app_id {
-                tracing::info!("Found our toplevel: app_id={}", info.app_id);
-                self.our_handle = info.cosmic_toplevel.clone();
-                self.our_foreign_handle = Some(toplevel.clone());
-                self.last_minimized = None;
-                self.last_activated = None;
-                let _ = self.event_tx.send(ToplevelEvent::Found);
-            }
-        }
+        self.note_title(toplevel);
+        self.try_adopt(toplevel);
+        self.report_toplevels(None);
+        self.report_focus();
     }
 
     fn update_toplevel(
@@ -147,6 +531,15 @@ impl ToplevelInfoHandler for WaylandState {
         _qh: &QueueHandle<Self>,
         toplevel: &ExtForeignToplevelHandleV1,
     ) {
+        // Some terminals set their app_id only after the surface is created,
+        // so a toplevel that didn't match in new_toplevel may match now.
+        if self.our_foreign_handle.is_none() {
+            self.note_title(toplevel);
+            self.try_adopt(toplevel);
+        }
+        self.report_toplevels(None);
+        self.report_focus();
+
         // Only track updates for the specific window we're managing
         let is_our_window = self
             .our_foreign_handle
@@ -170,6 +563,9 @@ impl ToplevelInfoHandler for WaylandState {
             if self.last_minimized != Some(is_minimized) {
                 self.last_minimized = Some(is_minimized);
                 if is_minimized {
+                    // Don't undo a minimize, e.g. by the user, by retrying
+                    // an activation
+                    self.activation_pending = None;
                     let _ = self.event_tx.send(ToplevelEvent::Minimized);
                 }
             }
@@ -177,6 +573,7 @@ impl ToplevelInfoHandler for WaylandState {
             if self.last_activated != Some(is_activated) {
                 self.last_activated = Some(is_activated);
                 if is_activated {
+                    self.activation_pending = None;
                     let _ = self.event_tx.send(ToplevelEvent::Activated);
                 } else {
                     let _ = self.event_tx.send(ToplevelEvent::Deactivated);
@@ -201,8 +598,13 @@ impl ToplevelInfoHandler for WaylandState {
             self.our_foreign_handle = None;
             self.last_minimized = None;
             self.last_activated = None;
+            self.parked = false;
             let _ = self.event_tx.send(ToplevelEvent::Closed);
         }
+        if self.titled.as_ref() == Some(toplevel) {
+            self.titled = None;
+        }
+        self.report_toplevels(Some(toplevel));
     }
 }
 
@@ -228,11 +630,14 @@ impl ToplevelManagerHandler for WaylandState {
 
 smithay_client_toolkit::delegate_registry!(WaylandState);
 smithay_client_toolkit::delegate_seat!(WaylandState);
+smithay_client_toolkit::delegate_output!(WaylandState);
 cosmic_client_toolkit::delegate_toplevel_info!(WaylandState);
 cosmic_client_toolkit::delegate_toplevel_manager!(WaylandState);
+cosmic_client_toolkit::delegate_workspace!(WaylandState);
 
 fn run_wayland_loop(
-    target_app_id: String,
+    target_app_ids: Vec<String>,
+    options: WaylandOptions,
     event_tx: tokio_mpsc::UnboundedSender<ToplevelEvent>,
     cmd_rx: std_mpsc::Receiver<WaylandCommand>,
 ) -> Result<(), Box<dyn std::error::Error>> {
@@ -240,8 +645,12 @@ fn run_wayland_loop(
     let (globals, mut event_queue) = registry_queue_init(&conn)?;
     let qh = event_queue.handle();
 
+    warn_if_not_cosmic(&globals);
+
     let registry = RegistryState::new(&globals);
     let seat_state = SeatState::new(&globals, &qh);
+    let output_state = OutputState::new(&globals, &qh);
+    let workspace_state = WorkspaceState::new(&registry, &qh);
     let toplevel_info = ToplevelInfoState::new(&registry, &qh);
     let toplevel_manager = ToplevelManagerState::try_new(&registry, &qh);
 
@@ -255,26 +664,44 @@ fn run_wayland_loop(
         toplevel_manager,
         seat_state,
         seat: None,
-        target_app_id,
+        warned_no_seat: false,
+        output_state,
+        workspace_state,
+        target_app_ids,
+        title_token: None,
+        titled: None,
+        options,
         our_handle: None,
         our_foreign_handle: None,
         event_tx,
         last_minimized: None,
         last_activated: None,
+        workspaces: Vec::new(),
+        toplevels: Vec::new(),
+        focused_output: None,
+        parked: false,
+        activation_pending: None,
     };
 
     // Initial roundtrip to discover globals and existing toplevels
     event_queue.roundtrip(&mut state)?;
+    let _ = state.event_tx.send(ToplevelEvent::Connected);
 
     loop {
         // Process commands from the app
-        while let Ok(cmd) = cmd_rx.try_recv() {
-            handle_command_inner(&state, cmd);
+        let pending: Vec<WaylandCommand> = cmd_rx.try_iter().collect();
+        for cmd in coalesce_commands(pending) {
+            if let WaylandCommand::Shutdown = cmd {
+                tracing::info!("Wayland loop shutting down");
+                return Ok(());
+            }
+            handle_command_inner(&mut state, cmd);
             let _ = conn.flush();
         }
 
         // Dispatch pending wayland events
         event_queue.dispatch_pending(&mut state)?;
+        retry_activation(&mut state);
         conn.flush()?;
 
         // Poll for new wayland events with a timeout
@@ -293,8 +720,325 @@ fn run_wayland_loop(
     }
 }
 
-fn handle_command_inner(state: &WaylandState, cmd: WaylandCommand) {
-    let Some(ref handle) = state.our_handle else {
+/// Activates our window a second time if the compositor didn't confirm the
+/// first activation in time, which it sometimes drops while restoring a
+/// minimized window. Retried once per show.
+fn retry_activation(state: &mut WaylandState) {
+    let Some(at) = state.activation_pending else {
+        return;
+    };
+    if Instant::now() < at {
+        return;
+    }
+    state.activation_pending = None;
+    let activated = state
+        .our_foreign_handle
+        .as_ref()
+        .and_then(|toplevel| state.toplevel_info.info(toplevel))
+        .is_some_and(|info| {
+            info.state
+                .contains(&zcosmic_toplevel_handle_v1::State::Activated)
+        });
+    if activated {
+        return;
+    }
+    if let (Some(handle), Some(manager_state), Some(seat)) = (
+        state.our_handle.as_ref(),
+        state.toplevel_manager.as_ref(),
+        state.seat.as_ref(),
+    ) {
+        tracing::info!("Terminal not activated after {ACTIVATION_TIMEOUT:?}, activating again");
+        manager_state.manager.activate(handle, seat);
+    }
+}
+
+/// Whether `cmd` sets the visibility of our window.
+fn is_visibility_command(cmd: &WaylandCommand) -> bool {
+    matches!(
+        cmd,
+        WaylandCommand::Minimize
+            | WaylandCommand::MoveToWorkspace(_)
+            | WaylandCommand::Activate
+            | WaylandCommand::ActivateHere
+    )
+}
+
+/// Drops commands a later one in the same batch supersedes. Rapid toggles
+/// queue show/hide commands faster than the compositor applies them, and
+/// sending them all lets them take effect out of order. Only the last of
+/// consecutive visibility commands, and the last of consecutive option
+/// updates, is kept; other commands keep their place and order.
+fn coalesce_commands(cmds: Vec<WaylandCommand>) -> Vec<WaylandCommand> {
+    let mut out: Vec<WaylandCommand> = Vec::with_capacity(cmds.len());
+    for cmd in cmds {
+        let supersedes = out.last().is_some_and(|prev| {
+            (is_visibility_command(prev) && is_visibility_command(&cmd))
+                || matches!(
+                    (prev, &cmd),
+                    (WaylandCommand::SetOptions(_), WaylandCommand::SetOptions(_))
+                )
+        });
+        if supersedes {
+            tracing::debug!("Dropping {:?}, superseded by {cmd:?}", out.last());
+            out.pop();
+        }
+        out.push(cmd);
+    }
+    out
+}
+
+/// Logs a prominent warning when the COSMIC toplevel protocols are missing,
+/// which usually means we are running under a different compositor.
+fn warn_if_not_cosmic(globals: &GlobalList) {
+    let (has_info, has_manager) = globals.contents().with_list(|list| {
+        (
+            list.iter()
+                .any(|g| g.interface == "zcosmic_toplevel_info_v1"),
+            list.iter()
+                .any(|g| g.interface == "zcosmic_toplevel_manager_v1"),
+        )
+    });
+
+    if has_info && has_manager {
+        return;
+    }
+
+    let desktop = std::env::var("XDG_CURRENT_DESKTOP").unwrap_or_default();
+    tracing::warn!(
+        "COSMIC toplevel protocols not found (info={has_info}, manager={has_manager}, \
+         XDG_CURRENT_DESKTOP={desktop:?}). This compositor does not appear to be COSMIC: \
+         the terminal can be spawned, but hiding and showing it will not work. \
+         Run the quake terminal inside a COSMIC session."
+    );
+}
+
+/// Returns the output our window is on and the workspaces of that output's
+/// group, ordered by their coordinates.
+fn window_workspaces(state: &WaylandState) -> Option<(WlOutput, Vec<&Workspace>)> {
+    let toplevel = state.our_foreign_handle.as_ref()?;
+    let info = state.toplevel_info.info(toplevel)?;
+    let output = info.output.iter().next()?.clone();
+    let group = state
+        .workspace_state
+        .workspace_groups()
+        .find(|g| g.outputs.contains(&output))?;
+
+    let mut workspaces: Vec<&Workspace> = group
+        .workspaces
+        .iter()
+        .filter_map(|h| state.workspace_state.workspace_info(h))
+        .collect();
+    workspaces.sort_by(|a, b| a.coordinates.cmp(&b.coordinates));
+    Some((output, workspaces))
+}
+
+/// All workspaces, numbered per group in the order `MoveToWorkspace` uses.
+fn workspace_list(state: &WaylandState) -> Vec<WorkspaceInfo> {
+    let mut list = Vec::new();
+    for group in state.workspace_state.workspace_groups() {
+        let outputs: Vec<String> = group
+            .outputs
+            .iter()
+            .filter_map(|o| state.output_state.info(o)?.name)
+            .collect();
+        let mut workspaces: Vec<&Workspace> = group
+            .workspaces
+            .iter()
+            .filter_map(|h| state.workspace_state.workspace_info(h))
+            .collect();
+        workspaces.sort_by(|a, b| a.coordinates.cmp(&b.coordinates));
+        list.extend(workspaces.iter().enumerate().map(|(i, w)| WorkspaceInfo {
+            name: w.name.clone(),
+            number: i + 1,
+            outputs: outputs.clone(),
+            active: w.state.contains(ext_workspace_handle_v1::State::Active),
+        }));
+    }
+    list
+}
+
+fn output_info(state: &WaylandState, output: &WlOutput) -> Option<OutputInfo> {
+    let info = state.output_state.info(output)?;
+    let scale = info.scale_factor.max(1);
+    let (x, y) = info.logical_position.unwrap_or((0, 0));
+    let (width, height) = info.logical_size.or_else(|| {
+        info.modes
+            .iter()
+            .find(|mode| mode.current)
+            .map(|mode| (mode.dimensions.0 / scale, mode.dimensions.1 / scale))
+    })?;
+
+    Some(OutputInfo {
+        name: info.name.clone().unwrap_or_else(|| String::from("unknown")),
+        rect: Rect {
+            x,
+            y,
+            width,
+            height,
+        },
+        scale,
+    })
+}
+
+/// The output of the currently activated toplevel, falling back to the
+/// first known output.
+fn focused_output(state: &WaylandState) -> Option<WlOutput> {
+    state
+        .toplevel_info
+        .toplevels()
+        .find(|info| {
+            info.state
+                .contains(&zcosmic_toplevel_handle_v1::State::Activated)
+        })
+        .and_then(|info| info.output.iter().next().cloned())
+        .or_else(|| state.output_state.outputs().next())
+}
+
+/// The connected output the `monitor` option names, if any.
+fn configured_output(state: &WaylandState) -> Option<WlOutput> {
+    match state.options.monitor {
+        Monitor::Focused => None,
+        Monitor::Primary => state.output_state.outputs().next(),
+        Monitor::Named(ref name) => {
+            let output = named_output(state, name);
+            if output.is_none() {
+                tracing::info!("Output {name} is not connected, showing the terminal in place");
+            }
+            output
+        }
+    }
+}
+
+/// The connected output called `name`.
+fn named_output(state: &WaylandState, name: &str) -> Option<WlOutput> {
+    state.output_state.outputs().find(|output| {
+        state
+            .output_state
+            .info(output)
+            .is_some_and(|info| info.name.as_deref() == Some(name))
+    })
+}
+
+/// The active workspace of `output` and that output, if our window is on
+/// a different output.
+fn output_workspace(
+    state: &WaylandState,
+    output: WlOutput,
+) -> Option<(ExtWorkspaceHandleV1, WlOutput)> {
+    let (window_output, _) = window_workspaces(state)?;
+    if window_output == output {
+        return None;
+    }
+    let group = state
+        .workspace_state
+        .workspace_groups()
+        .find(|g| g.outputs.contains(&output))?;
+    let active = group
+        .workspaces
+        .iter()
+        .filter_map(|h| state.workspace_state.workspace_info(h))
+        .find(|w| w.state.contains(ext_workspace_handle_v1::State::Active))?;
+    Some((active.handle.clone(), output))
+}
+
+/// Whether another window is both focused and fullscreen.
+fn fullscreen_focused(state: &WaylandState) -> bool {
+    state.toplevel_info.toplevels().any(|info| {
+        state.our_foreign_handle.as_ref() != Some(&info.foreign_toplevel)
+            && info
+                .state
+                .contains(&zcosmic_toplevel_handle_v1::State::Activated)
+            && info
+                .state
+                .contains(&zcosmic_toplevel_handle_v1::State::Fullscreen)
+    })
+}
+
+/// Adopts the first open toplevel matching the target app_ids, if none is
+/// adopted yet.
+fn adopt_existing(state: &mut WaylandState) {
+    let toplevels: Vec<_> = state
+        .toplevel_info
+        .toplevels()
+        .map(|info| info.foreign_toplevel.clone())
+        .collect();
+    for toplevel in &toplevels {
+        state.try_adopt(toplevel);
+    }
+}
+
+fn handle_command_inner(state: &mut WaylandState, cmd: WaylandCommand) {
+    // Commands that don't need our window
+    match cmd {
+        WaylandCommand::ReportFocusedOutput => {
+            let output = focused_output(state).and_then(|o| output_info(state, &o));
+            let _ = state.event_tx.send(ToplevelEvent::FocusedOutput(output));
+            return;
+        }
+        WaylandCommand::SetOptions(options) => {
+            state.options = options;
+            return;
+        }
+        WaylandCommand::SetTargets(app_ids) => {
+            tracing::debug!("Now looking for windows of {app_ids:?}");
+            state.target_app_ids = app_ids;
+            return;
+        }
+        WaylandCommand::ResetTracking => {
+            tracing::info!("Resetting window tracking");
+            let previous = state.our_foreign_handle.take();
+            state.our_handle = None;
+            state.last_minimized = None;
+            state.last_activated = None;
+            state.activation_pending = None;
+            adopt_existing(state);
+            state.adopt_titled();
+            // Only the same window can still be parked
+            state.parked &=
+                state.our_foreign_handle.is_some() && state.our_foreign_handle == previous;
+            let hidden = state
+                .our_foreign_handle
+                .as_ref()
+                .and_then(|handle| state.toplevel_info.info(handle))
+                .map(|info| {
+                    state.parked
+                        || info
+                            .state
+                            .contains(&zcosmic_toplevel_handle_v1::State::Minimized)
+                });
+            state.report_toplevels(None);
+            let _ = state.event_tx.send(ToplevelEvent::TrackingReset(hidden));
+            return;
+        }
+        WaylandCommand::WatchTitle(title) => {
+            state.title_token = title;
+            state.titled = None;
+            return;
+        }
+        WaylandCommand::AdoptTitled => {
+            if state.our_foreign_handle.is_none() && state.titled.is_none() {
+                tracing::info!("No window carried our title either");
+            }
+            state.adopt_titled();
+            return;
+        }
+        WaylandCommand::Rescan(app_ids) => {
+            state.target_app_ids = app_ids;
+            adopt_existing(state);
+            if state.our_foreign_handle.is_none() {
+                let app_ids = state
+                    .toplevel_info
+                    .toplevels()
+                    .map(|info| info.app_id.clone())
+                    .collect();
+                let _ = state.event_tx.send(ToplevelEvent::ScanMissed(app_ids));
+            }
+            return;
+        }
+        _ => {}
+    }
+
+    let Some(handle) = state.our_handle.clone() else {
         tracing::warn!("No toplevel handle, cannot execute command: {cmd:?}");
         return;
     };
@@ -303,55 +1047,298 @@ fn handle_command_inner(state: &WaylandState, cmd: WaylandCommand) {
         return;
     };
 
-    let manager = &manager_state.manager;
+    let manager = manager_state.manager.clone();
+    let handle = &handle;
+    let here = cmd == WaylandCommand::ActivateHere;
+    // A later command supersedes an unconfirmed activation
+    state.activation_pending = None;
 
     match cmd {
         WaylandCommand::Minimize => {
             manager.set_minimized(handle);
         }
-        WaylandCommand::Activate => {
+        WaylandCommand::MoveToWorkspace(n) => {
+            // move_to_ext_workspace was added in version 4
+            if manager.version() < 4 {
+                tracing::warn!("Compositor cannot move windows between workspaces, minimizing");
+                manager.set_minimized(handle);
+                return;
+            }
+            let Some((output, workspaces)) = window_workspaces(state) else {
+                tracing::warn!("Workspaces of the terminal's output are unknown, minimizing");
+                manager.set_minimized(handle);
+                return;
+            };
+            let Some(workspace) = (n as usize).checked_sub(1).and_then(|i| workspaces.get(i))
+            else {
+                tracing::warn!("Workspace {n} does not exist, minimizing");
+                manager.set_minimized(handle);
+                return;
+            };
+            manager.move_to_ext_workspace(handle, &workspace.handle, &output);
+            state.parked = true;
+        }
+        WaylandCommand::Activate | WaylandCommand::ActivateHere => {
+            if !state.options.raise_over_fullscreen && fullscreen_focused(state) {
+                tracing::info!("A fullscreen window is focused, not raising the terminal");
+                let _ = state.event_tx.send(ToplevelEvent::ActivationBlocked);
+                return;
+            }
+            let target = if here {
+                focused_output(state)
+            } else {
+                configured_output(state)
+            }
+            .and_then(|output| output_workspace(state, output));
+            if let Some((workspace, output)) = target {
+                if manager.version() >= 4 {
+                    manager.move_to_ext_workspace(handle, &workspace, &output);
+                    state.parked = false;
+                } else {
+                    tracing::warn!("Compositor cannot move windows between outputs");
+                }
+            }
+            if state.parked {
+                // Bring the window back from the parking workspace
+                if let Some((output, workspaces)) = window_workspaces(state) {
+                    if let Some(active) = workspaces
+                        .iter()
+                        .find(|w| w.state.contains(ext_workspace_handle_v1::State::Active))
+                    {
+                        manager.move_to_ext_workspace(handle, &active.handle, &output);
+                    }
+                }
+                state.parked = false;
+            }
             manager.unset_minimized(handle);
             if let Some(ref seat) = state.seat {
                 manager.activate(handle, seat);
+                state.activation_pending = Some(Instant::now() + ACTIVATION_TIMEOUT);
+            } else if !state.warned_no_seat {
+                // Headless/remote sessions may never advertise a seat; the
+                // window is still restored, it just can't receive focus.
+                tracing::warn!("No seat available, showing terminal without focusing it");
+                state.warned_no_seat = true;
             }
+            let output = state
+                .our_foreign_handle
+                .as_ref()
+                .and_then(|toplevel| state.toplevel_info.info(toplevel))
+                .and_then(|info| info.output.iter().next())
+                .and_then(|output| output_info(state, output));
+            let _ = state.event_tx.send(ToplevelEvent::Shown(output));
+        }
+        WaylandCommand::Close => {
+            manager.close(handle);
         }
+        // Handled above
+        WaylandCommand::ReportFocusedOutput
+        | WaylandCommand::SetOptions(_)
+        | WaylandCommand::Rescan(_)
+        | WaylandCommand::SetTargets(_)
+        | WaylandCommand::ResetTracking
+        | WaylandCommand::WatchTitle(_)
+        | WaylandCommand::AdoptTitled
+        | WaylandCommand::Shutdown => {}
     }
 }
 
-pub fn toplevel_subscription(target_app_id: String) -> cosmic::iced::Subscription<ToplevelEvent> {
+/// Runs the Wayland loop on its own thread. A panic in the loop is caught
+/// and reported as `ControllerLost` so the app can restart it.
+fn spawn_wayland_thread(
+    target_app_ids: Vec<String>,
+    options: WaylandOptions,
+    event_tx: tokio_mpsc::UnboundedSender<ToplevelEvent>,
+    cmd_rx: std_mpsc::Receiver<WaylandCommand>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let lost_tx = event_tx.clone();
+        // The panic hook has already printed the message and location by
+        // the time catch_unwind returns.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            run_wayland_loop(target_app_ids, options, event_tx, cmd_rx)
+        }));
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                tracing::error!("Wayland toplevel loop error: {e}");
+                let _ = lost_tx.send(ToplevelEvent::Disconnected);
+            }
+            Err(payload) => {
+                let message = payload
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                    .unwrap_or("non-string panic payload");
+                tracing::error!("Wayland toplevel loop panicked: {message}");
+                let _ = lost_tx.send(ToplevelEvent::ControllerLost);
+            }
+        }
+    })
+}
+
+/// `options` apply from the start, before the first toplevels are matched.
+/// `generation` is part of the subscription id, so bumping it restarts the
+/// Wayland loop after it was lost or disconnected.
+pub fn toplevel_subscription(
+    target_app_ids: Vec<String>,
+    options: WaylandOptions,
+    generation: u32,
+) -> cosmic::iced::Subscription<ToplevelEvent> {
     struct ToplevelSub;
 
     cosmic::iced::Subscription::run_with_id(
-        std::any::TypeId::of::<ToplevelSub>(),
-        futures::stream::unfold(ToplevelSubState::Init(target_app_id), |state| async move {
-            match state {
-                ToplevelSubState::Init(target_app_id) => {
-                    let (event_tx, event_rx) = tokio_mpsc::unbounded_channel();
-                    let (cmd_tx, cmd_rx) = std_mpsc::channel();
-
-                    let controller = WaylandController { cmd_tx };
-
-                    std::thread::spawn(move || {
-                        if let Err(e) = run_wayland_loop(target_app_id, event_tx, cmd_rx) {
-                            tracing::error!("Wayland toplevel loop error: {e}");
-                        }
-                    });
-
-                    Some((
-                        ToplevelEvent::Ready(controller),
-                        ToplevelSubState::Running(event_rx),
-                    ))
-                }
-                ToplevelSubState::Running(mut rx) => {
-                    let event = rx.recv().await?;
-                    Some((event, ToplevelSubState::Running(rx)))
+        (std::any::TypeId::of::<ToplevelSub>(), generation),
+        futures::stream::unfold(
+            ToplevelSubState::Init(target_app_ids, options),
+            |state| async move {
+                match state {
+                    ToplevelSubState::Init(target_app_ids, options) => {
+                        let (event_tx, event_rx) = tokio_mpsc::unbounded_channel();
+                        let (cmd_tx, cmd_rx) = std_mpsc::channel();
+
+                        let thread =
+                            spawn_wayland_thread(target_app_ids, options, event_tx, cmd_rx);
+                        let controller = WaylandController {
+                            cmd_tx,
+                            thread: Arc::new(Mutex::new(Some(thread))),
+                        };
+
+                        Some((
+                            ToplevelEvent::Ready(controller),
+                            ToplevelSubState::Running(event_rx),
+                        ))
+                    }
+                    ToplevelSubState::Running(mut rx) => {
+                        let event = rx.recv().await?;
+                        Some((event, ToplevelSubState::Running(rx)))
+                    }
                 }
-            }
-        }),
+            },
+        ),
     )
 }
 
 enum ToplevelSubState {
-    Init(String),
+    Init(Vec<String>, WaylandOptions),
     Running(tokio_mpsc::UnboundedReceiver<ToplevelEvent>),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_target_candidates_in_order() {
+        let targets = vec![
+            String::from("com.mitchellh.ghostty"),
+            String::from("com.mitchellh.ghostty-debug"),
+        ];
+        assert_eq!(
+            match_app_id("com.mitchellh.ghostty", &targets, &[]),
+            Some(0)
+        );
+        assert_eq!(
+            match_app_id("com.mitchellh.ghostty-debug", &targets, &[]),
+            Some(1)
+        );
+        assert_eq!(match_app_id("firefox", &targets, &[]), None);
+    }
+
+    #[test]
+    fn matches_x11_instance_and_class_pairs() {
+        let targets = vec![String::from("cosmic-ext-quake-terminal")];
+        assert_eq!(
+            match_app_id("xterm\0cosmic-ext-quake-terminal", &targets, &[]),
+            Some(0)
+        );
+        assert_eq!(
+            match_app_id("cosmic-ext-quake-terminal XTerm", &targets, &[]),
+            Some(0)
+        );
+        let targets = vec![String::from("xterm")];
+        assert_eq!(match_app_id("xterm XTerm", &targets, &[]), Some(0));
+        assert_eq!(match_app_id("XTerm", &targets, &[]), None);
+        assert_eq!(match_app_id("uxterm UXTerm", &targets, &[]), None);
+    }
+
+    #[test]
+    fn title_token_identifies_a_window_with_an_unexpected_app_id() {
+        assert!(matches_title("Alacritty", "Quake", Some("Quake"), &[]));
+        assert!(!matches_title("Alacritty", "~/src", Some("Quake"), &[]));
+        assert!(!matches_title("Alacritty", "Quake", None, &[]));
+        assert!(!matches_title(APP_ID, "Quake", Some("Quake"), &[]));
+        let ignored = vec![String::from("Alacritty")];
+        assert!(!matches_title(
+            "Alacritty",
+            "Quake",
+            Some("Quake"),
+            &ignored
+        ));
+    }
+
+    #[test]
+    fn never_matches_own_settings_window() {
+        let targets = vec![APP_ID.to_string()];
+        assert_eq!(match_app_id(APP_ID, &targets, &[]), None);
+    }
+
+    #[test]
+    fn ignored_app_ids_win_over_candidates() {
+        let targets = vec![
+            String::from("com.mitchellh.ghostty"),
+            String::from("com.mitchellh.ghostty-debug"),
+        ];
+        let ignored = vec![String::from("com.mitchellh.ghostty")];
+        assert_eq!(
+            match_app_id("com.mitchellh.ghostty", &targets, &ignored),
+            None
+        );
+        assert_eq!(
+            match_app_id("com.mitchellh.ghostty-debug", &targets, &ignored),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn rapid_toggles_collapse_to_last_visibility_command() {
+        let cmds = vec![
+            WaylandCommand::Activate,
+            WaylandCommand::Minimize,
+            WaylandCommand::Activate,
+            WaylandCommand::MoveToWorkspace(9),
+        ];
+        assert_eq!(
+            coalesce_commands(cmds),
+            vec![WaylandCommand::MoveToWorkspace(9)]
+        );
+    }
+
+    #[test]
+    fn other_commands_keep_their_order() {
+        let cmds = vec![
+            WaylandCommand::Minimize,
+            WaylandCommand::ReportFocusedOutput,
+            WaylandCommand::Activate,
+            WaylandCommand::Close,
+            WaylandCommand::SetOptions(WaylandOptions::default()),
+            WaylandCommand::SetOptions(WaylandOptions {
+                raise_over_fullscreen: false,
+                ..WaylandOptions::default()
+            }),
+        ];
+        assert_eq!(
+            coalesce_commands(cmds),
+            vec![
+                WaylandCommand::Minimize,
+                WaylandCommand::ReportFocusedOutput,
+                WaylandCommand::Activate,
+                WaylandCommand::Close,
+                WaylandCommand::SetOptions(WaylandOptions {
+                    raise_over_fullscreen: false,
+                    ..WaylandOptions::default()
+                }),
+            ]
+        );
+    }
+}